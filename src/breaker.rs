@@ -0,0 +1,195 @@
+// Standard library modules
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Stops sending traffic to a failing provider for a while, instead of waiting for its statistics to catch up.
+///
+/// The circuit of a provider opens after `consecutive_failures` failed probes or requests in a row, or once the
+/// share of failures within the latest `window` outcomes reaches `error_rate`. Open circuits are excluded from
+/// the selection. After `open_duration`, the circuit is half-open and the next probe is a trial: the circuit
+/// closes again if it succeeds and reopens otherwise.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{CircuitBreakerPolicy, SelectorConfig};
+/// use std::time::Duration;
+///
+/// let config = SelectorConfig {
+///     circuit_breaker: Some(CircuitBreakerPolicy {
+///         open_duration: Duration::from_secs(60),
+///         ..Default::default()
+///     }),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircuitBreakerPolicy {
+    /// The number of consecutive failures after which the circuit opens.
+    pub consecutive_failures: usize,
+
+    /// The share of failures within the window at which the circuit opens, between `0.0` and `1.0`.
+    pub error_rate: f64,
+
+    /// The number of latest outcomes the error rate is computed over. The error rate only opens the circuit
+    /// once the window is full.
+    pub window: usize,
+
+    /// The time the circuit stays open before a trial probe may close it.
+    pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        CircuitBreakerPolicy {
+            consecutive_failures: 5,
+            error_rate: 0.5,
+            window: 20,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The state of the circuit breaker of a provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CircuitState {
+    /// Traffic flows to the provider.
+    #[default]
+    Closed,
+
+    /// The provider failed too often and is excluded from the selection.
+    Open,
+
+    /// The provider was open long enough and is excluded until the next probe succeeds.
+    HalfOpen,
+}
+
+/// The circuit breaker of a single provider.
+#[derive(Debug, Clone)]
+pub(crate) struct CircuitBreaker {
+    /// Decides when the circuit opens and for how long.
+    policy: CircuitBreakerPolicy,
+
+    /// The latest outcomes recorded while the circuit was closed, oldest first. `true` stands for a failure.
+    outcomes: VecDeque<bool>,
+
+    /// The number of consecutive failures recorded while the circuit was closed.
+    consecutive_failures: usize,
+
+    /// When the circuit last opened, `None` while it is closed.
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed circuit breaker.
+    pub(crate) fn new(policy: CircuitBreakerPolicy) -> Self {
+        CircuitBreaker {
+            policy,
+            outcomes: VecDeque::with_capacity(policy.window),
+            consecutive_failures: 0,
+            opened_at: None,
+        }
+    }
+
+    /// Returns the state of the circuit.
+    pub(crate) fn state(&self) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() < self.policy.open_duration => {
+                CircuitState::Open
+            }
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Records the outcome of a probe or request, `trial` being whether it was a probe.
+    ///
+    /// Outcomes are ignored while the circuit is open, and only probes are trials while it is half-open.
+    pub(crate) fn record(&mut self, failed: bool, trial: bool) {
+        match self.state() {
+            CircuitState::Closed => {
+                if self.outcomes.len() >= self.policy.window.max(1) {
+                    self.outcomes.pop_front();
+                }
+                self.outcomes.push_back(failed);
+                self.consecutive_failures = if failed {
+                    self.consecutive_failures + 1
+                } else {
+                    0
+                };
+
+                // Open the circuit once either threshold is reached.
+                let failures = self.outcomes.iter().filter(|failed| **failed).count();
+                let window_full = self.outcomes.len() >= self.policy.window;
+                if self.consecutive_failures >= self.policy.consecutive_failures
+                    || (window_full
+                        && failures as f64 >= self.policy.error_rate * self.outcomes.len() as f64)
+                {
+                    self.open();
+                }
+            }
+            CircuitState::HalfOpen if trial => {
+                if failed {
+                    self.open();
+                } else {
+                    self.opened_at = None;
+                }
+            }
+            CircuitState::Open | CircuitState::HalfOpen => {}
+        }
+    }
+
+    /// Opens the circuit, forgetting the outcomes recorded while it was closed.
+    fn open(&mut self) {
+        self.opened_at = Some(Instant::now());
+        self.outcomes.clear();
+        self.consecutive_failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::breaker::{CircuitBreaker, CircuitBreakerPolicy, CircuitState};
+    use std::{thread::sleep, time::Duration};
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut breaker = CircuitBreaker::new(CircuitBreakerPolicy {
+            consecutive_failures: 3,
+            error_rate: 0.5,
+            window: 4,
+            open_duration: Duration::from_millis(50),
+        });
+
+        // Consecutive failures open the circuit.
+        for _ in 0..2 {
+            breaker.record(true, false);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record(true, true);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Only a successful probe closes a half-open circuit.
+        breaker.record(false, true);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.record(false, false);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.record(true, true);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        sleep(Duration::from_millis(60));
+        breaker.record(false, true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        // The error rate opens the circuit once the window is full.
+        for failed in [true, false, true] {
+            breaker.record(failed, false);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record(false, false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+}
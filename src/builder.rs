@@ -0,0 +1,463 @@
+// Standard library modules
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+// External libraries
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Url,
+};
+
+// Internal modules
+use crate::{
+    breaker::CircuitBreakerPolicy,
+    config::{
+        HealthPolicy, HedgePolicy, LatencyMode, RateLimitPolicy, ReadinessPolicy, RetryPolicy,
+        SelectorConfig,
+    },
+    error::BuildError,
+    probe::ProbeSpec,
+    stats::SelectionStatistic,
+    sticky::Stickiness,
+    strategy::SelectionStrategy,
+    transport::ipc_path,
+    ClosestWeb3RpcProviderSelector,
+};
+
+/// Builds a `ClosestWeb3RpcProviderSelector`, validating its providers and options.
+///
+/// Options that are not set keep the defaults of `SelectorConfig`.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, ProbeSpec};
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let balancer = ClosestWeb3RpcProviderSelector::builder()
+///         .provider("https://mainnet.infura.io/v3/your_api_key")
+///         .provider("https://rpc.ankr.com/eth")
+///         .checking_interval(Duration::from_secs(5))
+///         .probe(ProbeSpec::block_number())
+///         .expected_chain_id(1)
+///         .header("Authorization", "Bearer your_token")
+///         .build()
+///         .expect("Invalid balancer options");
+///
+///     balancer.destroy();
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct SelectorBuilder {
+    /// The URLs of the providers.
+    urls: Vec<String>,
+
+    /// The configuration of the balancer.
+    config: SelectorConfig,
+
+    /// The names and values of the headers, validated when building the balancer.
+    headers: Vec<(String, String)>,
+}
+
+impl SelectorBuilder {
+    /// Adds the provider at `url`.
+    ///
+    /// HTTP, WebSocket and IPC providers are supported, IPC sockets being given as `ipc://` URLs or absolute
+    /// paths.
+    pub fn provider(mut self, url: impl Into<String>) -> Self {
+        self.urls.push(url.into());
+        self
+    }
+
+    /// Adds the providers at `urls`.
+    pub fn providers<I>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.urls.extend(urls.into_iter().map(Into::into));
+        self
+    }
+
+    /// Replaces the whole configuration, e.g. to start from a shared one. Headers added with `header` are
+    /// sent in addition to the configured ones.
+    pub fn config(mut self, config: SelectorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the interval at which the response times of the providers are checked.
+    pub fn checking_interval(mut self, checking_interval: Duration) -> Self {
+        self.config.checking_interval = checking_interval;
+        self
+    }
+
+    /// Sets the maximum time spent establishing a connection to a provider.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.config.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Sets the maximum time a single probe request may take.
+    pub fn probe_timeout(mut self, probe_timeout: Duration) -> Self {
+        self.config.probe_timeout = probe_timeout;
+        self
+    }
+
+    /// Sets the maximum time a round of probes may take.
+    pub fn round_deadline(mut self, round_deadline: Duration) -> Self {
+        self.config.round_deadline = Some(round_deadline);
+        self
+    }
+
+    /// Sets the JSON-RPC call used to measure the response times.
+    pub fn probe(mut self, probe: ProbeSpec) -> Self {
+        self.config.probe = probe;
+        self
+    }
+
+    /// Sets the maximum number of blocks a provider may lag behind the highest observed block.
+    pub fn max_block_lag(mut self, max_block_lag: u64) -> Self {
+        self.config.max_block_lag = Some(max_block_lag);
+        self
+    }
+
+    /// Sets the chain ID every provider must report.
+    pub fn expected_chain_id(mut self, expected_chain_id: u64) -> Self {
+        self.config.expected_chain_id = Some(expected_chain_id);
+        self
+    }
+
+    /// Sets the interval at which the chain ID of every provider is verified again.
+    pub fn chain_id_check_interval(mut self, chain_id_check_interval: Duration) -> Self {
+        self.config.chain_id_check_interval = chain_id_check_interval;
+        self
+    }
+
+    /// Sets the number of latest probe samples kept per provider.
+    pub fn sample_window(mut self, sample_window: usize) -> Self {
+        self.config.sample_window = sample_window;
+        self
+    }
+
+    /// Sets the smoothing factor of the moving average of the response times.
+    pub fn ewma_alpha(mut self, ewma_alpha: f64) -> Self {
+        self.config.ewma_alpha = ewma_alpha;
+        self
+    }
+
+    /// Sets the statistic providers are ranked by.
+    pub fn selection_statistic(mut self, selection_statistic: SelectionStatistic) -> Self {
+        self.config.selection_statistic = selection_statistic;
+        self
+    }
+
+    /// Sets the strategy choosing the provider returned by `get_fastest_provider`.
+    pub fn strategy(mut self, strategy: impl SelectionStrategy + 'static) -> Self {
+        self.config.strategy = Arc::new(strategy);
+        self
+    }
+
+    /// Keeps returning the same provider until a challenger is consistently faster.
+    pub fn stickiness(mut self, stickiness: Stickiness) -> Self {
+        self.config.stickiness = Some(stickiness);
+        self
+    }
+
+    /// Sets when failing providers are considered down.
+    pub fn health(mut self, health: HealthPolicy) -> Self {
+        self.config.health = health;
+        self
+    }
+
+    /// Sets how long providers rate limiting the balancer cool down.
+    pub fn rate_limit(mut self, rate_limit: RateLimitPolicy) -> Self {
+        self.config.rate_limit = rate_limit;
+        self
+    }
+
+    /// Enables a circuit breaker on every provider, excluding providers failing too often until they recover.
+    pub fn circuit_breaker(mut self, circuit_breaker: CircuitBreakerPolicy) -> Self {
+        self.config.circuit_breaker = Some(circuit_breaker);
+        self
+    }
+
+    /// Sets the client used to probe HTTP providers.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.config.http_client = Some(http_client);
+        self
+    }
+
+    /// Sets whether HTTP providers are probed over warm pooled connections, new connections, or both.
+    pub fn latency_mode(mut self, latency_mode: LatencyMode) -> Self {
+        self.config.latency_mode = latency_mode;
+        self
+    }
+
+    /// Sets when the balancer is ready.
+    pub fn readiness(mut self, readiness: ReadinessPolicy) -> Self {
+        self.config.readiness = readiness;
+        self
+    }
+
+    /// Sets how requests sent through the balancer are retried on other providers.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Sets the weight of real requests in the latency statistics, between `0.0` and `1.0`.
+    pub fn passive_weight(mut self, passive_weight: f64) -> Self {
+        self.config.passive_weight = passive_weight;
+        self
+    }
+
+    /// Hedges requests sent through the balancer on the next provider when the current one is slow to answer.
+    pub fn hedge(mut self, hedge: HedgePolicy) -> Self {
+        self.config.hedge = Some(hedge);
+        self
+    }
+
+    /// Adds a header sent with every HTTP probe and WebSocket handshake.
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Validates the providers and options, then starts the balancer.
+    ///
+    /// This has to be called from within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BuildError` if no provider was given, if a provider URL is invalid or given twice, if a
+    /// header is invalid, or if an option is out of its valid range.
+    pub fn build(self) -> Result<ClosestWeb3RpcProviderSelector, BuildError> {
+        let SelectorBuilder {
+            urls,
+            mut config,
+            headers,
+        } = self;
+
+        // Validate the providers.
+        if urls.is_empty() {
+            return Err(BuildError::NoProviders);
+        }
+        let mut seen = HashSet::new();
+        for url in &urls {
+            validate_url(url)?;
+            if !seen.insert(url.as_str()) {
+                return Err(BuildError::DuplicateProvider(url.clone()));
+            }
+        }
+
+        // Validate the headers.
+        for (name, value) in headers {
+            let invalid = |reason: String| BuildError::InvalidHeader {
+                name: name.clone(),
+                reason,
+            };
+            let header_name =
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(e.to_string()))?;
+            let header_value = HeaderValue::from_str(&value).map_err(|e| invalid(e.to_string()))?;
+            config.headers.append(header_name, header_value);
+        }
+
+        // Validate the options.
+        validate_config(&config, urls.len())?;
+
+        Ok(ClosestWeb3RpcProviderSelector::with_config(urls, config))
+    }
+}
+
+/// Checks that a provider URL can be reached by one of the transports.
+pub(crate) fn validate_url(url: &str) -> Result<(), BuildError> {
+    let invalid = |reason: String| BuildError::InvalidUrl {
+        url: url.to_string(),
+        reason,
+    };
+
+    // Absolute paths and `.ipc` paths are IPC sockets, other URLs must have a supported scheme.
+    if let Some(path) = ipc_path(url) {
+        if path.as_os_str().is_empty() {
+            return Err(invalid("Missing socket path".to_string()));
+        }
+        return Ok(());
+    }
+
+    let parsed = Url::parse(url).map_err(|e| invalid(e.to_string()))?;
+    match parsed.scheme() {
+        "http" | "https" | "ws" | "wss" => {}
+        scheme => return Err(invalid(format!("Unsupported scheme {}", scheme))),
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("Missing host".to_string()));
+    }
+    Ok(())
+}
+
+/// Checks that every option of `config` is within its valid range for a balancer of `providers` providers.
+fn validate_config(config: &SelectorConfig, providers: usize) -> Result<(), BuildError> {
+    let invalid = |option: &'static str, reason: &str| {
+        Err(BuildError::InvalidOption {
+            option,
+            reason: reason.to_string(),
+        })
+    };
+
+    if config.checking_interval.is_zero() {
+        return invalid("checking_interval", "Must not be zero");
+    }
+    if config.connect_timeout.is_zero() {
+        return invalid("connect_timeout", "Must not be zero");
+    }
+    if config.probe_timeout.is_zero() {
+        return invalid("probe_timeout", "Must not be zero");
+    }
+    if config
+        .round_deadline
+        .is_some_and(|deadline| deadline.is_zero())
+    {
+        return invalid("round_deadline", "Must not be zero");
+    }
+    if config.sample_window == 0 {
+        return invalid("sample_window", "Must keep at least one sample");
+    }
+    if !(config.ewma_alpha > 0.0 && config.ewma_alpha <= 1.0) {
+        return invalid("ewma_alpha", "Must be greater than 0 and at most 1");
+    }
+    if !(0.0..=1.0).contains(&config.passive_weight) {
+        return invalid("passive_weight", "Must be between 0 and 1");
+    }
+    if config.health.failures_before_down == 0 {
+        return invalid("failures_before_down", "Must be at least 1");
+    }
+    if config.rate_limit.default_cooldown > config.rate_limit.max_cooldown {
+        return invalid("default_cooldown", "Must not exceed max_cooldown");
+    }
+    if let Some(circuit_breaker) = &config.circuit_breaker {
+        if circuit_breaker.consecutive_failures == 0 {
+            return invalid("consecutive_failures", "Must be at least 1");
+        }
+        if !(circuit_breaker.error_rate > 0.0 && circuit_breaker.error_rate <= 1.0) {
+            return invalid("error_rate", "Must be greater than 0 and at most 1");
+        }
+        if circuit_breaker.window == 0 {
+            return invalid("window", "Must be at least 1");
+        }
+        if circuit_breaker.open_duration.is_zero() {
+            return invalid("open_duration", "Must not be zero");
+        }
+    }
+    if config.retry.max_attempts == 0 {
+        return invalid("max_attempts", "Must be at least 1");
+    }
+    if config.retry.attempt_timeout.is_zero() {
+        return invalid("attempt_timeout", "Must not be zero");
+    }
+    validate_readiness(config.readiness, providers)
+}
+
+/// Checks that a balancer of `providers` providers can satisfy the `readiness` policy.
+pub(crate) fn validate_readiness(
+    readiness: ReadinessPolicy,
+    providers: usize,
+) -> Result<(), BuildError> {
+    if let ReadinessPolicy::AtLeast(required) = readiness {
+        if required > providers {
+            return Err(BuildError::InvalidOption {
+                option: "readiness",
+                reason: format!(
+                    "Requires {} available providers but the balancer has only {}",
+                    required, providers
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BuildError, ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, ReadinessPolicy};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_build_validates_options() {
+        assert_eq!(
+            ClosestWeb3RpcProviderSelector::builder().build().err(),
+            Some(BuildError::NoProviders)
+        );
+        assert_eq!(
+            ClosestWeb3RpcProviderSelector::builder()
+                .providers([
+                    "https://a.example",
+                    "https://b.example",
+                    "https://a.example"
+                ])
+                .build()
+                .err(),
+            Some(BuildError::DuplicateProvider(
+                "https://a.example".to_string()
+            ))
+        );
+        for url in [
+            "ftp://a.example",
+            "http://",
+            "https://a b.example",
+            "ipc://",
+            "localhost:8545",
+            "rpc.ankr.com/eth",
+            "geth.ipc",
+        ] {
+            let Some(BuildError::InvalidUrl { url: invalid, .. }) =
+                ClosestWeb3RpcProviderSelector::builder()
+                    .provider(url)
+                    .build()
+                    .err()
+            else {
+                panic!("Expected {} to be rejected", url);
+            };
+            assert_eq!(invalid, url);
+        }
+        assert!(matches!(
+            ClosestWeb3RpcProviderSelector::builder()
+                .provider("https://a.example")
+                .header("Invalid Name", "value")
+                .build()
+                .err(),
+            Some(BuildError::InvalidHeader { .. })
+        ));
+        assert!(matches!(
+            ClosestWeb3RpcProviderSelector::builder()
+                .provider("https://a.example")
+                .checking_interval(Duration::ZERO)
+                .build()
+                .err(),
+            Some(BuildError::InvalidOption {
+                option: "checking_interval",
+                ..
+            })
+        ));
+        assert!(matches!(
+            ClosestWeb3RpcProviderSelector::builder()
+                .provider("https://a.example")
+                .readiness(ReadinessPolicy::AtLeast(2))
+                .build()
+                .err(),
+            Some(BuildError::InvalidOption {
+                option: "readiness",
+                ..
+            })
+        ));
+
+        // Valid options start the balancer.
+        let provider = ClosestWeb3RpcProviderSelector::builder()
+            .providers(["https://a.example", "wss://b.example", "/tmp/geth.ipc"])
+            .checking_interval(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        provider.destroy();
+    }
+}
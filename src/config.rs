@@ -0,0 +1,297 @@
+// Standard library modules
+use std::{sync::Arc, time::Duration};
+
+// External libraries
+use reqwest::header::HeaderMap;
+
+// Internal modules
+use crate::{
+    breaker::CircuitBreakerPolicy,
+    probe::ProbeSpec,
+    stats::SelectionStatistic,
+    sticky::Stickiness,
+    strategy::{LowestLatency, SelectionStrategy},
+};
+
+/// Configuration of a `ClosestWeb3RpcProviderSelector`.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{ProbeSpec, SelectorConfig};
+/// use std::time::Duration;
+///
+/// let config = SelectorConfig {
+///     checking_interval: Duration::from_secs(5),
+///     probe: ProbeSpec::block_number(),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct SelectorConfig {
+    /// The interval at which the response times of the providers are checked.
+    pub checking_interval: Duration,
+
+    /// The maximum time spent establishing a connection to a provider.
+    pub connect_timeout: Duration,
+
+    /// The maximum time a single probe request may take, including connecting to the provider.
+    pub probe_timeout: Duration,
+
+    /// The maximum time a round of probes may take. Providers that did not answer by then are recorded as
+    /// timed out. `None` uses the checking interval.
+    pub round_deadline: Option<Duration>,
+
+    /// The JSON-RPC call used to measure the response times.
+    pub probe: ProbeSpec,
+
+    /// The maximum number of blocks a provider may lag behind the highest observed block before it is
+    /// only selected when no provider within the limit is available. `None` disables block lag tracking.
+    ///
+    /// When enabled, `eth_blockNumber` is requested from every provider on each round.
+    pub max_block_lag: Option<u64>,
+
+    /// The chain ID every provider must report through `eth_chainId`. Providers reporting another chain ID,
+    /// or whose chain ID could not be verified yet, are quarantined. `None` disables the verification.
+    pub expected_chain_id: Option<u64>,
+
+    /// The interval at which the chain ID of every provider is verified again.
+    pub chain_id_check_interval: Duration,
+
+    /// The number of latest probe samples kept per provider to compute its statistics.
+    pub sample_window: usize,
+
+    /// The smoothing factor of the moving average of the response times, between `0.0` and `1.0`.
+    /// Higher values give more weight to recent samples.
+    pub ewma_alpha: f64,
+
+    /// The statistic providers are ranked by.
+    pub selection_statistic: SelectionStatistic,
+
+    /// The strategy choosing the provider returned by `get_fastest_provider`.
+    pub strategy: Arc<dyn SelectionStrategy>,
+
+    /// Keeps returning the same provider until the strategy consistently prefers another one. `None` selects
+    /// the provider chosen by the strategy on every call.
+    pub stickiness: Option<Stickiness>,
+
+    /// Decides when failing providers are considered down and excluded from the selection.
+    pub health: HealthPolicy,
+
+    /// Decides how long providers rate limiting the balancer are excluded from the selection and the probes.
+    pub rate_limit: RateLimitPolicy,
+
+    /// Excludes providers failing too often from the selection until they recover. `None` disables the
+    /// circuit breakers.
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+
+    /// The client used to probe HTTP providers. `None` creates one per balancer, built with the connect timeout.
+    ///
+    /// Connections of the client are reused across rounds, so inject a client shared with the rest of the
+    /// application to measure the latency its requests actually experience. Cold probes open their own
+    /// connections and ignore the client, including its proxies, root certificates and identity.
+    pub http_client: Option<reqwest::Client>,
+
+    /// Whether HTTP providers are probed over warm pooled connections, new connections, or both.
+    pub latency_mode: LatencyMode,
+
+    /// The headers sent with every HTTP probe and WebSocket handshake, e.g. to authenticate with the providers.
+    pub headers: HeaderMap,
+
+    /// Decides when the balancer is ready, as reported by `is_ready` and awaited by `wait_until_ready`.
+    pub readiness: ReadinessPolicy,
+
+    /// Decides how requests sent through the balancer are retried on other providers.
+    pub retry: RetryPolicy,
+
+    /// Sends requests to the next provider of the ranking as well when the current one is slow to answer.
+    /// `None` only tries another provider once an attempt failed.
+    pub hedge: Option<HedgePolicy>,
+
+    /// The weight of real requests in the latest and average latencies providers are ranked by, between `0.0`
+    /// and `1.0`.
+    ///
+    /// Requests sent through the balancer and outcomes reported with `report_outcome` are blended with the
+    /// probe samples: `0.0` ignores them, `1.0` ranks providers by real requests alone once they served one.
+    /// Percentiles are never blended, so ranking by a percentile only considers the probes.
+    pub passive_weight: f64,
+}
+
+/// Decides how requests sent through `ClosestWeb3RpcProviderSelector::request` are retried.
+///
+/// Requests failing because of the transport are retried on the next provider of the ranking, as are requests
+/// answered with one of the retryable JSON-RPC error codes. Rate limiting answers are always retried on the next
+/// provider, whatever the retryable codes. Other JSON-RPC errors are returned right away, since another provider
+/// would answer the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of providers a request is sent to, including the first one.
+    pub max_attempts: usize,
+
+    /// The maximum time a provider may take to answer before the request is retried on the next provider.
+    pub attempt_timeout: Duration,
+
+    /// The JSON-RPC error codes worth retrying on another provider.
+    pub retryable_codes: Vec<i64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            attempt_timeout: Duration::from_secs(10),
+            // Internal error and resource unavailable.
+            retryable_codes: vec![-32603, -32002],
+        }
+    }
+}
+
+/// Decides how long providers rate limiting the balancer cool down.
+///
+/// A provider answering with an HTTP 429 status or a rate limiting JSON-RPC error is neither selected nor probed
+/// for the time it asked for through the `Retry-After` header or the error data, or for `default_cooldown` if it
+/// did not say. Cooldowns never exceed `max_cooldown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    /// The cooldown of providers that did not say how long to wait.
+    pub default_cooldown: Duration,
+
+    /// The maximum cooldown, whatever the provider asked for.
+    pub max_cooldown: Duration,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        RateLimitPolicy {
+            default_cooldown: Duration::from_secs(10),
+            max_cooldown: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Decides when a request sent through `ClosestWeb3RpcProviderSelector::request` is hedged.
+///
+/// When a provider did not answer within the hedging delay, the same request is sent to the next provider of
+/// the ranking without cancelling the first one. The first valid answer is returned and the other attempts are
+/// cancelled. Hedged requests count towards `RetryPolicy::max_attempts`.
+///
+/// Only idempotent methods are hedged: transactions, signatures and filter methods are sent to one provider at a
+/// time, since a duplicate could be answered with an error although the first call succeeded.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{HedgePolicy, SelectorConfig};
+/// use std::time::Duration;
+///
+/// let config = SelectorConfig {
+///     hedge: Some(HedgePolicy::P95 {
+///         fallback: Duration::from_millis(200),
+///     }),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HedgePolicy {
+    /// Hedges requests after a fixed delay.
+    Fixed(Duration),
+
+    /// Hedges requests after the 95th percentile of the response times of the provider, so that only its
+    /// slowest answers are hedged.
+    P95 {
+        /// The delay used while the percentile of the provider is unknown.
+        fallback: Duration,
+    },
+}
+
+/// Decides when a balancer is ready to provide the fastest provider.
+///
+/// A provider is available when it answered successfully within its sample window, it is neither down nor
+/// quarantined because of a chain ID mismatch, its circuit is closed and it is not cooling down after rate
+/// limiting the balancer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadinessPolicy {
+    /// The balancer is ready as soon as one provider is available.
+    #[default]
+    AnyAvailable,
+
+    /// The balancer is ready once at least this many providers are available.
+    AtLeast(usize),
+
+    /// The balancer is ready once every provider was probed at least once and one of them is available, so
+    /// that no provider is left out of the first selection.
+    AllProbedOnce,
+}
+
+/// Whether HTTP providers are probed over warm pooled connections or over new connections.
+///
+/// Persistent WebSocket and IPC connections are always warm. Cold probes open direct connections with the
+/// system root certificates, without the proxies, root certificates or identity of the HTTP client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LatencyMode {
+    /// Probes reuse the pooled connections of the HTTP client, like regular requests do.
+    #[default]
+    Warm,
+
+    /// Every probe opens a new connection, so response times include the DNS lookup and the TCP and TLS
+    /// handshakes.
+    Cold,
+
+    /// Providers are ranked by probes over warm connections, and every round additionally measures the
+    /// response time over a new connection, reported as `cold_latency` in the provider statistics.
+    ColdAndWarm,
+}
+
+/// Decides when failing providers are considered down.
+///
+/// A provider whose latest probe failed is degraded and only selected when no healthy provider is available.
+/// Once `failures_before_down` consecutive probes failed, it is down and never selected until a probe succeeds.
+/// A timed out probe counts as `timeout_penalty` failures, since a hanging provider is more disruptive than
+/// one failing fast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthPolicy {
+    /// The number of consecutive failed probes after which a provider is considered down.
+    pub failures_before_down: usize,
+
+    /// The number of failures a timed out probe counts as.
+    pub timeout_penalty: usize,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        HealthPolicy {
+            failures_before_down: 2,
+            timeout_penalty: 2,
+        }
+    }
+}
+
+impl Default for SelectorConfig {
+    fn default() -> Self {
+        SelectorConfig {
+            checking_interval: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(3),
+            probe_timeout: Duration::from_secs(5),
+            round_deadline: None,
+            probe: ProbeSpec::default(),
+            max_block_lag: None,
+            expected_chain_id: None,
+            chain_id_check_interval: Duration::from_secs(300),
+            sample_window: 20,
+            ewma_alpha: 0.3,
+            selection_statistic: SelectionStatistic::default(),
+            strategy: Arc::new(LowestLatency),
+            stickiness: None,
+            health: HealthPolicy::default(),
+            rate_limit: RateLimitPolicy::default(),
+            circuit_breaker: None,
+            http_client: None,
+            latency_mode: LatencyMode::default(),
+            headers: HeaderMap::new(),
+            readiness: ReadinessPolicy::default(),
+            retry: RetryPolicy::default(),
+            hedge: None,
+            passive_weight: 0.5,
+        }
+    }
+}
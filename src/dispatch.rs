@@ -0,0 +1,411 @@
+// Standard library modules
+use std::{sync::Arc, time::Duration};
+
+// External libraries
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use serde_json::{json, Value};
+use tokio::time::{timeout, timeout_at, Instant};
+
+// Internal modules
+use crate::{
+    config::HedgePolicy,
+    error::{ProbeError, RequestError, SelectorError},
+    transport::Transport,
+    ClosestWeb3RpcProviderSelector, ProviderUrl,
+};
+
+/// Prefixes of the JSON-RPC methods that are not hedged because they change the state of the provider or the
+/// chain: a duplicate call could fail although the first one succeeded, e.g. with a transaction already known.
+const NON_IDEMPOTENT_METHODS: [&str; 8] = [
+    "eth_send",
+    "eth_sign",
+    "eth_submit",
+    "eth_new",
+    "eth_uninstallFilter",
+    "eth_getFilterChanges",
+    "eth_subscribe",
+    "personal_",
+];
+
+/// The result of a request sent through the balancer, along with the provider that served it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcResponse {
+    /// The URL of the provider that answered the request.
+    pub provider: ProviderUrl,
+
+    /// The result of the call.
+    pub result: Value,
+
+    /// The URLs of the providers whose attempt failed, along with the error of each attempt. Hedged attempts
+    /// cancelled once another provider answered are not listed.
+    pub failed_attempts: Vec<(ProviderUrl, ProbeError)>,
+}
+
+impl ClosestWeb3RpcProviderSelector {
+    /// Calls `method` with `params` on the selected provider, retrying on the next providers of the ranking
+    /// according to the retry policy, and hedging on them when the provider is slow if a hedge policy is set.
+    ///
+    /// Methods that are not idempotent, such as `eth_sendRawTransaction`, `eth_sign*` or the filter methods,
+    /// are never hedged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    /// use serde_json::json;
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec![
+    ///         "https://rpc.ankr.com/eth".to_string(),
+    ///         "https://eth.llamarpc.com".to_string(),
+    ///     ];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     match balancer.request("eth_blockNumber", json!([])).await {
+    ///         Ok(response) => println!("{} answered {}", response.provider, response.result),
+    ///         Err(e) => eprintln!("{}", e),
+    ///     }
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `RequestError::Unavailable` if no provider is available, `RequestError::JsonRpc` if a provider
+    /// answered with a JSON-RPC error that is not retried, or `RequestError::AllAttemptsFailed` otherwise.
+    pub async fn request(&self, method: &str, params: Value) -> Result<RpcResponse, RequestError> {
+        self.send_raw(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1
+        }))
+        .await
+    }
+
+    /// Sends the JSON-RPC `request` object to the selected provider, retrying and hedging on the next providers
+    /// of the ranking like `request`.
+    ///
+    /// The `id` of the request may be replaced for providers reached over persistent connections.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RequestError::InvalidRequest` if `request` is not a JSON object, and the same errors as
+    /// `request` otherwise.
+    pub async fn send_raw(&self, request: Value) -> Result<RpcResponse, RequestError> {
+        if !request.is_object() {
+            return Err(RequestError::InvalidRequest(
+                "Expected a JSON-RPC request object".to_string(),
+            ));
+        }
+
+        // Find the providers to try, best first.
+        let order = self.failover_order();
+        if order.is_empty() {
+            return Err(RequestError::Unavailable(
+                self.try_get_fastest_provider()
+                    .err()
+                    .unwrap_or(SelectorError::NotReady),
+            ));
+        }
+
+        // Only hedge methods that can safely be sent twice.
+        let hedged = request
+            .get("method")
+            .and_then(Value::as_str)
+            .is_some_and(|method| {
+                !NON_IDEMPOTENT_METHODS
+                    .iter()
+                    .any(|prefix| method.starts_with(prefix))
+            });
+
+        // Send the request to one provider at a time, moving on to the next provider on transport and retryable
+        // errors, or as well when hedging and the pending attempts are slow to answer.
+        let retry = &self.inner.config.retry;
+        let mut order = order
+            .into_iter()
+            .filter_map(|url| {
+                self.inner
+                    .transports
+                    .get(&url)
+                    .map(|transport| (url, transport))
+            })
+            .take(retry.max_attempts);
+        let mut attempts = FuturesUnordered::new();
+        let mut next_attempt_at = None;
+        let mut failed_attempts = Vec::new();
+        loop {
+            if attempts.is_empty() || next_attempt_at.is_some_and(|at| at <= Instant::now()) {
+                next_attempt_at = None;
+                match order.next() {
+                    Some((url, transport)) => {
+                        next_attempt_at = self
+                            .hedge_delay(&url)
+                            .filter(|_| hedged)
+                            .map(|delay| Instant::now() + delay);
+                        attempts.push(self.attempt(url, transport, &request));
+                    }
+                    None if attempts.is_empty() => break,
+                    None => {}
+                }
+            }
+
+            // Wait for the first attempt to complete, or until it is time to hedge.
+            let completed = match next_attempt_at {
+                Some(at) => match timeout_at(at, attempts.next()).await {
+                    Ok(completed) => completed,
+                    Err(_) => continue,
+                },
+                None => attempts.next().await,
+            };
+            let Some((url, response)) = completed else {
+                continue;
+            };
+
+            // Return the first valid answer, cancelling the other attempts.
+            match response {
+                Ok(result) => {
+                    return Ok(RpcResponse {
+                        provider: url,
+                        result,
+                        failed_attempts,
+                    })
+                }
+                Err(ProbeError::JsonRpc { code, message })
+                    if !retry.retryable_codes.contains(&code) =>
+                {
+                    return Err(RequestError::JsonRpc {
+                        provider: url,
+                        code,
+                        message,
+                    })
+                }
+                Err(e) => {
+                    failed_attempts.push((url, e));
+                    next_attempt_at = Some(Instant::now());
+                }
+            }
+        }
+
+        Err(RequestError::AllAttemptsFailed {
+            attempts: failed_attempts,
+        })
+    }
+
+    /// Sends the `request` to the provider at `url` and reports the outcome to its statistics.
+    async fn attempt(
+        &self,
+        url: ProviderUrl,
+        transport: Arc<Transport>,
+        request: &Value,
+    ) -> (ProviderUrl, Result<Value, ProbeError>) {
+        let retry = &self.inner.config.retry;
+        let response = timeout(
+            retry.attempt_timeout,
+            transport.request(self.inner.transports.client(), request, false),
+        )
+        .await
+        .map_err(|_| ProbeError::Timeout)
+        .and_then(|response| response);
+
+        // Report the outcome, counting errors that another provider would answer the same as successes.
+        let response = match response {
+            Ok(timed_response) => {
+                let result = timed_response.response.into_result();
+                match &result {
+                    Err(ProbeError::JsonRpc { code, .. })
+                        if !retry.retryable_codes.contains(code) =>
+                    {
+                        self.report_outcome(&url, Ok(timed_response.response_time))
+                    }
+                    Err(e) => self.report_outcome(&url, Err(e.clone())),
+                    Ok(_) => self.report_outcome(&url, Ok(timed_response.response_time)),
+                }
+                result
+            }
+            Err(e) => {
+                self.report_outcome(&url, Err(e.clone()));
+                Err(e)
+            }
+        };
+        (url, response)
+    }
+
+    /// Returns how long to wait for the provider at `url` before hedging a request on the next provider, `None`
+    /// if requests are not hedged.
+    fn hedge_delay(&self, url: &str) -> Option<Duration> {
+        match self.inner.config.hedge? {
+            HedgePolicy::Fixed(delay) => Some(delay),
+            HedgePolicy::P95 { fallback } => Some(
+                self.inner
+                    .provider_states
+                    .lock()
+                    .unwrap()
+                    .get(url)
+                    .and_then(|state| state.stats().p95)
+                    .unwrap_or(fallback),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mock::MockProvider, ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, HedgePolicy,
+        ProbeError, RequestError, SelectorConfig, SelectorError,
+    };
+    use serde_json::json;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::time::{sleep, Instant};
+
+    #[tokio::test]
+    async fn test_request_failover() {
+        // The fastest provider fails balance requests and reverts calls.
+        let failing_url = MockProvider::new()
+            .handler(|method, _| match method {
+                "eth_getBalance" => json!({"error": {"code": -32603, "message": "Internal error"}}),
+                "eth_call" => json!({"error": {"code": 3, "message": "execution reverted"}}),
+                _ => json!("mock/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .handler(|method, _| match method {
+                "eth_getBalance" => json!("0x2a"),
+                _ => json!("mock/v1.0.0"),
+            })
+            .spawn_ws()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![failing_url.clone(), url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            provider.request("eth_getBalance", json!([])).await,
+            Err(RequestError::Unavailable(SelectorError::NotReady))
+        );
+        provider.wait_until_ready().await;
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(provider.get_fastest_provider(), failing_url);
+
+        // Other errors are returned right away and count as answers.
+        assert_eq!(
+            provider.request("eth_call", json!([])).await,
+            Err(RequestError::JsonRpc {
+                provider: failing_url.clone(),
+                code: 3,
+                message: "execution reverted".to_string()
+            })
+        );
+
+        // Retryable errors are retried on the next provider.
+        let response = provider
+            .request("eth_getBalance", json!(["0x0", "latest"]))
+            .await
+            .unwrap();
+        assert_eq!(response.provider, url);
+        assert_eq!(response.result, json!("0x2a"));
+        assert_eq!(
+            response.failed_attempts,
+            vec![(
+                failing_url.clone(),
+                ProbeError::JsonRpc {
+                    code: -32603,
+                    message: "Internal error".to_string()
+                }
+            )]
+        );
+
+        // Every attempt is reported to the statistics of its provider.
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&failing_url].passive_samples, 2);
+        assert_eq!(stats[&url].passive_samples, 1);
+        assert!(stats[&url].passive_latency.is_some());
+
+        // Raw requests must be JSON-RPC request objects.
+        assert!(matches!(
+            provider.send_raw(json!("eth_chainId")).await,
+            Err(RequestError::InvalidRequest(_))
+        ));
+        let response = provider
+            .send_raw(
+                json!({"jsonrpc": "2.0", "method": "web3_clientVersion", "params": [], "id": 7}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.result, json!("mock/v1.0.0"));
+        assert!(response.failed_attempts.is_empty());
+
+        provider.destroy();
+        assert_eq!(
+            provider.request("eth_getBalance", json!([])).await,
+            Err(RequestError::Unavailable(SelectorError::Destroyed))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hedged_request() {
+        // The fastest provider becomes slow once the balancer is ready.
+        let slow = Arc::new(AtomicBool::new(false));
+        let fastest_url = MockProvider::new()
+            .delays({
+                let slow = slow.clone();
+                move |_| {
+                    if slow.load(Ordering::SeqCst) {
+                        Duration::from_secs(2)
+                    } else {
+                        Duration::ZERO
+                    }
+                }
+            })
+            .spawn()
+            .await;
+        let url = MockProvider::new()
+            .delay(Duration::from_millis(30))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::builder()
+            .providers([fastest_url.clone(), url.clone()])
+            .checking_interval(Duration::from_secs(10))
+            .hedge(HedgePolicy::Fixed(Duration::from_millis(100)))
+            .build()
+            .unwrap();
+        provider.wait_until_ready().await;
+        assert_eq!(provider.get_fastest_provider(), fastest_url);
+        slow.store(true, Ordering::SeqCst);
+
+        // The next provider answers first and the slow attempt is cancelled without being reported.
+        let started_at = Instant::now();
+        let response = provider
+            .request("web3_clientVersion", json!([]))
+            .await
+            .unwrap();
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+        assert_eq!(response.provider, url);
+        assert!(response.failed_attempts.is_empty());
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&fastest_url].passive_samples, 0);
+        assert_eq!(stats[&url].passive_samples, 1);
+
+        // Transactions are not hedged, the slow provider is waited for.
+        let response = provider
+            .request("eth_sendRawTransaction", json!(["0x00"]))
+            .await
+            .unwrap();
+        assert_eq!(response.provider, fastest_url);
+        assert!(response.failed_attempts.is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+// Standard library modules
+use std::{error::Error, fmt, time::Duration};
+
+// Internal modules
+use crate::ProviderUrl;
+
+/// A provider reported a chain ID different from the expected one and was quarantined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainIdMismatch {
+    /// The URL of the quarantined provider.
+    pub url: String,
+
+    /// The chain ID the balancer was configured with.
+    pub expected: u64,
+
+    /// The chain ID reported by the provider.
+    pub actual: u64,
+}
+
+impl fmt::Display for ChainIdMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Provider {} is on chain {} instead of chain {}",
+            self.url, self.actual, self.expected
+        )
+    }
+}
+
+impl Error for ChainIdMismatch {}
+
+/// Error returned when the balancer cannot provide a provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SelectorError {
+    /// No provider was probed yet, or the chain IDs of the providers are still being verified. Wait until the
+    /// balancer is ready.
+    NotReady,
+
+    /// The balancer was destroyed and no longer probes its providers.
+    Destroyed,
+
+    /// Every provider is down, has an open circuit or is cooling down after rate limiting the balancer.
+    AllProvidersUnhealthy {
+        /// The URLs of the providers that are unavailable, along with the error of their latest failed probe.
+        down: Vec<(ProviderUrl, ProbeError)>,
+    },
+
+    /// Every provider that is not down reported an unexpected chain ID.
+    ChainIdMismatch(Vec<ChainIdMismatch>),
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectorError::NotReady => write!(f, "The balancer is not ready yet"),
+            SelectorError::Destroyed => write!(f, "The balancer was destroyed"),
+            SelectorError::AllProvidersUnhealthy { down } => {
+                write!(f, "No healthy provider available")?;
+                for (url, error) in down {
+                    write!(f, ", {} is down: {}", url, error)?;
+                }
+                Ok(())
+            }
+            SelectorError::ChainIdMismatch(mismatches) => {
+                write!(f, "No provider on the expected chain")?;
+                for mismatch in mismatches {
+                    write!(f, ", {}", mismatch)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for SelectorError {}
+
+/// The reason a probe of a provider failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProbeError {
+    /// The host name of the provider could not be resolved.
+    Dns(String),
+
+    /// The provider refused the connection.
+    ConnectRefused(String),
+
+    /// The TLS handshake with the provider failed.
+    Tls(String),
+
+    /// The provider did not answer in time.
+    Timeout,
+
+    /// The provider answered with an unsuccessful HTTP status code.
+    HttpStatus(u16),
+
+    /// The provider rejected the request because too many requests were sent, with an HTTP 429 status or a
+    /// rate limiting JSON-RPC error.
+    RateLimited {
+        /// The time the provider asked to wait before sending more requests, if it said.
+        retry_after: Option<Duration>,
+    },
+
+    /// The response is not a valid JSON-RPC response.
+    MalformedJson(String),
+
+    /// The provider answered with a JSON-RPC error.
+    JsonRpc {
+        /// The error code.
+        code: i64,
+
+        /// The error message.
+        message: String,
+    },
+
+    /// The result does not have the shape expected by the probe.
+    UnexpectedResult(String),
+
+    /// The connection to the provider failed or was lost for another reason.
+    Connection(String),
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Dns(message) => write!(f, "DNS resolution failed: {}", message),
+            ProbeError::ConnectRefused(message) => write!(f, "Connection refused: {}", message),
+            ProbeError::Tls(message) => write!(f, "TLS handshake failed: {}", message),
+            ProbeError::Timeout => write!(f, "Request timed out"),
+            ProbeError::HttpStatus(code) => write!(f, "Received HTTP status {}", code),
+            ProbeError::RateLimited { retry_after: None } => {
+                write!(f, "Rate limited by the provider")
+            }
+            ProbeError::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "Rate limited by the provider, retry after {:?}",
+                retry_after
+            ),
+            ProbeError::MalformedJson(message) => write!(f, "Malformed response: {}", message),
+            ProbeError::JsonRpc { code, message } => {
+                write!(f, "Received JSON-RPC error {}: {}", code, message)
+            }
+            ProbeError::UnexpectedResult(result) => {
+                write!(f, "Received unexpected result: {}", result)
+            }
+            ProbeError::Connection(message) => write!(f, "Connection failed: {}", message),
+        }
+    }
+}
+
+impl Error for ProbeError {}
+
+/// Error returned when a request sent through the balancer failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestError {
+    /// The request is not a JSON-RPC request object.
+    InvalidRequest(String),
+
+    /// No provider is available to send the request to.
+    Unavailable(SelectorError),
+
+    /// A provider answered with a JSON-RPC error that is not retried on other providers.
+    JsonRpc {
+        /// The URL of the provider that answered.
+        provider: ProviderUrl,
+
+        /// The error code.
+        code: i64,
+
+        /// The error message.
+        message: String,
+    },
+
+    /// Every attempt failed, either because of the transport or with a retryable JSON-RPC error.
+    AllAttemptsFailed {
+        /// The URLs of the attempted providers, in order, along with the error of each attempt.
+        attempts: Vec<(ProviderUrl, ProbeError)>,
+    },
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::InvalidRequest(message) => write!(f, "Invalid request: {}", message),
+            RequestError::Unavailable(error) => write!(f, "No provider available: {}", error),
+            RequestError::JsonRpc {
+                provider,
+                code,
+                message,
+            } => write!(
+                f,
+                "Provider {} answered with JSON-RPC error {}: {}",
+                provider, code, message
+            ),
+            RequestError::AllAttemptsFailed { attempts } => {
+                write!(f, "Every attempt failed")?;
+                for (url, error) in attempts {
+                    write!(f, ", {}: {}", url, error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for RequestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RequestError::Unavailable(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned when a balancer is built with invalid options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// No provider was given, or removing a provider would leave the balancer without any.
+    NoProviders,
+
+    /// A provider URL cannot be reached by any transport.
+    InvalidUrl {
+        /// The invalid URL.
+        url: String,
+
+        /// Why the URL is invalid.
+        reason: String,
+    },
+
+    /// The same provider was given more than once.
+    DuplicateProvider(String),
+
+    /// A header has an invalid name or value.
+    InvalidHeader {
+        /// The name of the header.
+        name: String,
+
+        /// Why the header is invalid.
+        reason: String,
+    },
+
+    /// An option has a value out of its valid range.
+    InvalidOption {
+        /// The name of the option.
+        option: &'static str,
+
+        /// Why the value is invalid.
+        reason: String,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::NoProviders => write!(f, "A balancer needs at least one provider"),
+            BuildError::InvalidUrl { url, reason } => {
+                write!(f, "Invalid provider URL {}: {}", url, reason)
+            }
+            BuildError::DuplicateProvider(url) => write!(f, "Provider {} was given twice", url),
+            BuildError::InvalidHeader { name, reason } => {
+                write!(f, "Invalid header {}: {}", name, reason)
+            }
+            BuildError::InvalidOption { option, reason } => {
+                write!(f, "Invalid {}: {}", option, reason)
+            }
+        }
+    }
+}
+
+impl Error for BuildError {}
@@ -0,0 +1,18 @@
+// Internal modules
+use crate::error::ChainIdMismatch;
+
+/// Events emitted by a `ClosestWeb3RpcProviderSelector` while it monitors its providers.
+///
+/// Subscribe to them with `ClosestWeb3RpcProviderSelector::subscribe`.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SelectorEvent {
+    /// A provider reported an unexpected chain ID and is excluded from the selection.
+    ChainIdMismatch(ChainIdMismatch),
+
+    /// A provider was added to the balancer and is probed from the next round on.
+    ProviderAdded(String),
+
+    /// A provider was removed from the balancer, along with its statistics.
+    ProviderRemoved(String),
+}
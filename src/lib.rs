@@ -1,38 +1,58 @@
 // Standard library modules
 use std::{
     collections::HashMap,
-    error::Error,
-    fmt,
-    sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
 // External libraries
-use serde::Deserialize;
-use serde_json::Value;
-use tokio::{sync::watch, time::sleep};
-
-/// Represents a JSON-RPC response with an optional error field.
-#[derive(Debug, Deserialize)]
-struct JsonRpcResponse {
-    /// Optional error message or object.
-    error: Option<Value>,
-}
+use tokio::sync::{broadcast, watch};
 
-/// A custom error type for representing errors within the library.
-#[derive(Debug)]
-struct LibError {
-    /// The error message.
-    message: String,
-}
+// Internal modules
+mod breaker;
+mod builder;
+mod config;
+mod dispatch;
+mod error;
+mod events;
+#[cfg(test)]
+mod mock;
+mod probe;
+mod prober;
+mod stats;
+mod sticky;
+mod strategy;
+mod transport;
 
-impl fmt::Display for LibError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
-    }
-}
+pub use breaker::{CircuitBreakerPolicy, CircuitState};
+pub use builder::SelectorBuilder;
+pub use config::{
+    HealthPolicy, HedgePolicy, LatencyMode, RateLimitPolicy, ReadinessPolicy, RetryPolicy,
+    SelectorConfig,
+};
+pub use dispatch::RpcResponse;
+pub use error::{BuildError, ChainIdMismatch, ProbeError, RequestError, SelectorError};
+pub use events::SelectorEvent;
+pub use probe::{ExpectedResult, ProbeSpec};
+pub use stats::{LatencyPhases, ProviderHealth, ProviderStats, SelectionStatistic};
+pub use sticky::{Stickiness, SwitchMargin};
+pub use strategy::{
+    Candidate, LowestLatency, PowerOfTwoChoices, PriorityTiers, RoundRobinTopK, SelectionStrategy,
+    WeightedRandom,
+};
+
+/// The URL of a Web3 provider.
+pub type ProviderUrl = String;
 
-impl Error for LibError {}
+// Internal items
+use prober::Prober;
+use stats::ProviderState;
+use sticky::StickySelection;
+use strategy::Ranking;
+use transport::TransportPool;
 
 /// Defines methods for interacting with a Web3 provider balancer.
 /// This trait enables you to:
@@ -70,11 +90,12 @@ pub trait ClosestWeb3Provider {
     /// * `checking_interval` - The interval at which the balancer checks the response times of the providers.
     fn init(urls: Vec<String>, checking_interval: Duration) -> Self;
 
-    /// Checks if the balancer is ready to provide the fastest provider.
+    /// Checks if the balancer is ready to provide the fastest provider, according to the configured readiness
+    /// policy.
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
     /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
     /// use std::time::Duration;
     ///
@@ -104,6 +125,9 @@ pub trait ClosestWeb3Provider {
 
     /// Stops the balancer and clears its data.
     ///
+    /// The balancer also stops when it is dropped, so this is only needed to stop it early. Calling it more
+    /// than once has no effect.
+    ///
     /// # Example
     ///
     /// ```
@@ -120,7 +144,7 @@ pub trait ClosestWeb3Provider {
     ///
     ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers.clone(), Duration::from_secs(10));
     ///
-    ///     balancer.destroy(); // **This step is essential!**
+    ///     balancer.destroy();
     /// }
     /// ```
     fn destroy(&self);
@@ -129,7 +153,7 @@ pub trait ClosestWeb3Provider {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
     /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
     /// use std::time::Duration;
     ///
@@ -153,18 +177,28 @@ pub trait ClosestWeb3Provider {
     ///
     /// # Returns
     ///
-    /// The URL of the provider with the fastest response time.
+    /// The URL of the provider with the fastest response time, or the provider chosen by the configured
+    /// selection strategy.
+    /// When a maximum block lag is configured, providers lagging behind the chain head are only returned
+    /// if no other provider is available. Providers that are down or never answered successfully are never
+    /// returned. When stickiness is configured, the previously selected provider is returned until a
+    /// challenger is consistently faster.
     ///
     /// # Panics
     ///
-    /// This function will panic if the hashmap containing response times is empty.
+    /// This function will panic if the hashmap containing response times is empty, or if every provider is
+    /// down or quarantined because of a chain ID mismatch. Use `try_get_fastest_provider` to handle each of
+    /// these cases instead.
     fn get_fastest_provider(&self) -> String;
 
-    /// Waits until the balancer is ready to provide the fastest provider.
+    /// Waits until the balancer is ready to provide the fastest provider, or until it is destroyed.
+    ///
+    /// The balancer is checked again whenever a round of probes completes. This waits forever if no provider
+    /// ever becomes available; use `wait_until_ready_timeout` to bound the wait.
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
     /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
     /// use std::time::Duration;
     ///
@@ -187,197 +221,704 @@ pub trait ClosestWeb3Provider {
 
 /// A concrete implementation of the `ClosestWeb3Provider` trait that balances Web3 providers based on their response times.
 /// This struct:
-/// * Internally tracks response times for each provided URL.
+/// * Internally tracks rolling response time statistics for each provided URL.
 /// * Periodically checks response times to update its internal map.
 /// * Provides methods to access the fastest provider and its URL.
 /// * Allows waiting until the fastest provider is available.
 ///
+/// * Can be cloned cheaply to share it across tasks. Clones share the same background task and statistics, which
+///   stop when any clone is destroyed or the last clone is dropped.
+///
 /// This implementation offers a convenient way to manage and utilize multiple Web3 providers while ensuring optimal performance.
+#[derive(Clone)]
 pub struct ClosestWeb3RpcProviderSelector {
+    /// The state shared by every clone of the balancer.
+    inner: Arc<SelectorInner>,
+}
+
+/// The state of a balancer, shared by all of its clones.
+struct SelectorInner {
     /// Sender for sending messages to the response time check task.
     interval_handle: watch::Sender<()>,
 
-    /// Shared map storing the response time for each provider.
-    current_response_time_per_url: Arc<Mutex<HashMap<String, u128>>>,
+    /// The URLs of the providers, shared with the response time check task which picks up changes on its
+    /// next round.
+    providers: Arc<Mutex<Vec<String>>>,
+
+    /// The transports of the providers, shared with the response time check task.
+    transports: Arc<TransportPool>,
+
+    /// Whether the balancer was destroyed.
+    destroyed: AtomicBool,
+
+    /// Shared map storing the measurements of each provider.
+    provider_states: Arc<Mutex<HashMap<String, ProviderState>>>,
+
+    /// The provider kept selected when stickiness is enabled.
+    selection: Arc<Mutex<StickySelection>>,
+
+    /// The configuration of the balancer.
+    config: Arc<SelectorConfig>,
+
+    /// Sender broadcasting the events of the balancer to its subscribers.
+    events: broadcast::Sender<SelectorEvent>,
+
+    /// Receiver notified whenever a round of probes completes, holding the number of completed rounds.
+    rounds: watch::Receiver<usize>,
 }
 
 impl ClosestWeb3Provider for ClosestWeb3RpcProviderSelector {
     fn init(urls: Vec<String>, checking_interval: Duration) -> Self {
+        Self::with_config(
+            urls,
+            SelectorConfig {
+                checking_interval,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn is_ready(&self) -> bool {
+        // Count the providers that may be selected.
+        let available = self
+            .inner
+            .provider_states
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|state| state.is_available(&self.inner.config))
+            .count();
+
+        match self.inner.config.readiness {
+            ReadinessPolicy::AnyAvailable => available > 0,
+            ReadinessPolicy::AtLeast(providers) => available >= providers.max(1),
+            ReadinessPolicy::AllProbedOnce => *self.inner.rounds.borrow() > 0 && available > 0,
+        }
+    }
+
+    fn destroy(&self) {
+        self.inner.destroy();
+    }
+
+    fn get_fastest_provider(&self) -> String {
+        self.try_get_fastest_provider()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    async fn wait_until_ready(&self) {
+        let _ = self.wait_for_readiness().await;
+    }
+}
+
+impl SelectorInner {
+    /// Stops the response time check task and clears the provider state map.
+    fn destroy(&self) {
+        // Only the first call stops the balancer.
+        if self.destroyed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Send a message to stop the response time check task, unless it already stopped.
+        let _ = self.interval_handle.send(());
+
+        // Clear the provider state map.
+        self.provider_states.lock().unwrap().clear();
+    }
+}
+
+impl Drop for SelectorInner {
+    fn drop(&mut self) {
+        // Stop the response time check task once the last clone of the balancer is dropped.
+        self.destroy();
+    }
+}
+
+impl ClosestWeb3RpcProviderSelector {
+    /// Initializes the provider balancer with a list of URLs and a custom configuration.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, ProbeSpec, SelectorConfig};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec![
+    ///         "https://mainnet.infura.io/v3/your_api_key".to_string(),
+    ///         "https://rpc.ankr.com/eth".to_string(),
+    ///     ];
+    ///
+    ///     let config = SelectorConfig {
+    ///         checking_interval: Duration::from_secs(10),
+    ///         probe: ProbeSpec::block_number(),
+    ///         ..Default::default()
+    ///     };
+    ///     let balancer = ClosestWeb3RpcProviderSelector::with_config(providers, config);
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `urls` - A vector of URLs for the Web3 providers.
+    /// * `config` - The configuration of the balancer.
+    pub fn with_config(urls: Vec<String>, config: SelectorConfig) -> Self {
         // Create a channel for sending messages to the response time check task.
         let (tx, rx) = watch::channel(());
 
-        // Create a shared map to store response times.
-        let current_response_time_per_url = Arc::new(Mutex::new(HashMap::new()));
+        // Create a channel for broadcasting events to subscribers.
+        let (events, _) = broadcast::channel(64);
+
+        // Create a channel notifying the balancer of completed rounds.
+        let (rounds_tx, rounds) = watch::channel(0);
+
+        // Create a shared list of the providers and a shared map to store their measurements.
+        let providers = Arc::new(Mutex::new(urls));
+        let provider_states = Arc::new(Mutex::new(HashMap::new()));
+        let selection = Arc::new(Mutex::new(StickySelection::default()));
+        let config = Arc::new(config);
+        let transports = Arc::new(TransportPool::new(&config));
 
         // Spawn a task to periodically check response times.
-        tokio::spawn(Self::process_response_time_check(
-            urls.clone(),
-            rx,
-            current_response_time_per_url.clone(),
-            checking_interval,
-        ));
+        let prober = Prober::new(
+            providers.clone(),
+            transports.clone(),
+            provider_states.clone(),
+            selection.clone(),
+            config.clone(),
+            events.clone(),
+            rounds_tx,
+        );
+        tokio::spawn(prober.run(rx));
 
         // Return the ClosestWeb3RpcProviderSelector instance.
         ClosestWeb3RpcProviderSelector {
-            interval_handle: tx,
-            current_response_time_per_url,
+            inner: Arc::new(SelectorInner {
+                interval_handle: tx,
+                providers,
+                transports,
+                destroyed: AtomicBool::new(false),
+                provider_states,
+                selection,
+                config,
+                events,
+                rounds,
+            }),
         }
     }
 
-    fn is_ready(&self) -> bool {
-        // Check if the response time map has any entries.
-        self.current_response_time_per_url.lock().unwrap().len() > 0
+    /// Returns a builder validating the providers and options of a new balancer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, ReadinessPolicy};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let balancer = ClosestWeb3RpcProviderSelector::builder()
+    ///         .providers(["https://rpc.ankr.com/eth", "wss://ethereum-rpc.publicnode.com"])
+    ///         .probe_timeout(Duration::from_secs(2))
+    ///         .readiness(ReadinessPolicy::AtLeast(2))
+    ///         .build()
+    ///         .expect("Invalid balancer options");
+    ///
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    pub fn builder() -> SelectorBuilder {
+        SelectorBuilder::default()
     }
 
-    fn destroy(&self) {
-        // Send a message to stop the response time check task.
-        self.interval_handle
-            .send(())
-            .expect("Failed to send DESTROY message to interval_handle");
+    /// Stops the balancer and waits until its background task has stopped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     balancer.shutdown().await;
+    /// }
+    /// ```
+    pub async fn shutdown(&self) {
+        let stopped = self.stopped();
+        self.destroy();
+        stopped.await;
+    }
+
+    /// Returns a future that completes once the background task of the balancer has stopped, after it was
+    /// destroyed or dropped.
+    ///
+    /// The future does not borrow the balancer, so it can be awaited after dropping it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     let stopped = balancer.stopped();
+    ///     drop(balancer);
+    ///     stopped.await;
+    /// }
+    /// ```
+    pub fn stopped(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut rounds = self.inner.rounds.clone();
 
-        // Clear the response time map.
-        self.current_response_time_per_url.lock().unwrap().clear();
+        // The prober drops the sender once it stops.
+        async move { while rounds.changed().await.is_ok() {} }
     }
 
-    fn get_fastest_provider(&self) -> String {
-        // Lock the response time map and find the provider with the lowest response time.
-        let binding = self.current_response_time_per_url.lock().unwrap();
-        let (key, _) = binding.iter().min_by_key(|(_, &v)| v).unwrap();
+    /// Waits until the balancer is ready to provide the fastest provider, for at most `timeout`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     match balancer.wait_until_ready_timeout(Duration::from_secs(5)).await {
+    ///         Ok(()) => println!("Fastest provider: {}", balancer.get_fastest_provider()),
+    ///         Err(e) => eprintln!("{}", e),
+    ///     }
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `SelectorError::Destroyed` if the balancer is destroyed while waiting. Otherwise, if the balancer
+    /// is not ready in time, returns the reason why no provider qualifies, or `SelectorError::NotReady` if
+    /// providers are available but not enough to satisfy the readiness policy.
+    pub async fn wait_until_ready_timeout(&self, timeout: Duration) -> Result<(), SelectorError> {
+        match tokio::time::timeout(timeout, self.wait_for_readiness()).await {
+            Ok(result) => result,
+            Err(_) => Err(self
+                .try_get_fastest_provider()
+                .err()
+                .unwrap_or(SelectorError::NotReady)),
+        }
+    }
+
+    /// Subscribes to the events emitted by the balancer, such as chain ID mismatches.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, SelectorEvent};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     let mut events = balancer.subscribe();
+    ///     tokio::spawn(async move {
+    ///         while let Ok(event) = events.recv().await {
+    ///             if let SelectorEvent::ChainIdMismatch(mismatch) = event {
+    ///                 eprintln!("{}", mismatch);
+    ///             }
+    ///         }
+    ///     });
+    ///
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    pub fn subscribe(&self) -> broadcast::Receiver<SelectorEvent> {
+        self.inner.events.subscribe()
+    }
 
-        // Clone and return the URL of the fastest provider.
-        key.clone()
+    /// Returns the URLs of the providers of the balancer.
+    pub fn providers(&self) -> Vec<ProviderUrl> {
+        self.inner.providers.lock().unwrap().clone()
     }
 
-    async fn wait_until_ready(&self) {
-        loop {
-            if self.is_ready() {
-                break;
+    /// Adds the provider at `url`, which is probed from the next round on.
+    ///
+    /// A `SelectorEvent::ProviderAdded` event is emitted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     balancer
+    ///         .add_provider("https://eth.llamarpc.com")
+    ///         .expect("Invalid provider");
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `BuildError::InvalidUrl` if the URL is invalid, or `BuildError::DuplicateProvider` if the provider
+    /// was already added.
+    pub fn add_provider(&self, url: impl Into<String>) -> Result<(), BuildError> {
+        let url = url.into();
+        builder::validate_url(&url)?;
+
+        let mut providers = self.inner.providers.lock().unwrap();
+        if providers.contains(&url) {
+            return Err(BuildError::DuplicateProvider(url));
+        }
+        providers.push(url.clone());
+
+        let _ = self.inner.events.send(SelectorEvent::ProviderAdded(url));
+        Ok(())
+    }
+
+    /// Removes the provider at `url` along with its statistics, so it is no longer selected. Its connection is
+    /// closed on the next round.
+    ///
+    /// A `SelectorEvent::ProviderRemoved` event is emitted.
+    ///
+    /// # Returns
+    ///
+    /// * `true` if the provider was removed; `false` if it was not a provider of the balancer.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BuildError` without removing the provider if it is the last one of the balancer, or if the
+    /// remaining providers could not satisfy a `ReadinessPolicy::AtLeast` policy.
+    pub fn remove_provider(&self, url: &str) -> Result<bool, BuildError> {
+        let mut providers = self.inner.providers.lock().unwrap();
+        let Some(position) = providers.iter().position(|provider| provider == url) else {
+            return Ok(false);
+        };
+        if providers.len() == 1 {
+            return Err(BuildError::NoProviders);
+        }
+        builder::validate_readiness(self.inner.config.readiness, providers.len() - 1)?;
+        providers.remove(position);
+        self.inner.provider_states.lock().unwrap().remove(url);
+
+        let _ = self
+            .inner
+            .events
+            .send(SelectorEvent::ProviderRemoved(url.to_string()));
+        Ok(true)
+    }
+
+    /// Replaces the providers of the balancer with the ones at `urls`.
+    ///
+    /// Providers kept across the change keep their statistics and connections. Removed providers are no longer
+    /// selected, added providers are probed from the next round on. A `SelectorEvent::ProviderRemoved` or
+    /// `SelectorEvent::ProviderAdded` event is emitted for every change.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BuildError` without changing the providers if `urls` is empty, if a URL is invalid or given
+    /// twice, or if the providers could not satisfy a `ReadinessPolicy::AtLeast` policy.
+    pub fn replace_providers(&self, urls: Vec<String>) -> Result<(), BuildError> {
+        // Validate every URL before changing anything.
+        if urls.is_empty() {
+            return Err(BuildError::NoProviders);
+        }
+        builder::validate_readiness(self.inner.config.readiness, urls.len())?;
+        for (index, url) in urls.iter().enumerate() {
+            builder::validate_url(url)?;
+            if urls[..index].contains(url) {
+                return Err(BuildError::DuplicateProvider(url.clone()));
             }
-            sleep(Duration::from_millis(10)).await;
         }
+
+        let mut providers = self.inner.providers.lock().unwrap();
+        let removed: Vec<String> = providers
+            .iter()
+            .filter(|url| !urls.contains(url))
+            .cloned()
+            .collect();
+        let added: Vec<String> = urls
+            .iter()
+            .filter(|url| !providers.contains(url))
+            .cloned()
+            .collect();
+        *providers = urls;
+
+        // Drop the statistics of the removed providers and report the changes.
+        let mut provider_states = self.inner.provider_states.lock().unwrap();
+        for url in removed {
+            provider_states.remove(&url);
+            let _ = self.inner.events.send(SelectorEvent::ProviderRemoved(url));
+        }
+        for url in added {
+            let _ = self.inner.events.send(SelectorEvent::ProviderAdded(url));
+        }
+        Ok(())
     }
-}
 
-impl ClosestWeb3RpcProviderSelector {
-    /// Asynchronously checks the response times of the providers and updates the response time map.
-    async fn process_response_time_check(
-        urls: Vec<String>,
-        receiver: watch::Receiver<()>,
-        response_times: Arc<Mutex<HashMap<String, u128>>>,
-        checking_interval: Duration,
-    ) {
-        loop {
-            // Clone the receiver to avoid borrowing issues within the select macro.
-            let mut receiver_clone = receiver.clone();
+    /// Reports the outcome of a real request sent to the provider at `url`: its response time, or the error it
+    /// failed with.
+    ///
+    /// Response times are blended with the latest and average latencies of the probes according to
+    /// `SelectorConfig::passive_weight`, and failures count towards the health of the provider like failed
+    /// probes. Requests sent with `request` and `send_raw` are reported automatically. Outcomes of providers not
+    /// probed yet are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, ProbeError};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     balancer.wait_until_ready().await;
+    ///     let url = balancer.get_fastest_provider();
+    ///     // ... send a request to `url` and measure it ...
+    ///     balancer.report_outcome(&url, Ok(Duration::from_millis(120)));
+    ///     balancer.report_outcome(&url, Err(ProbeError::HttpStatus(503)));
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    pub fn report_outcome(&self, url: &str, outcome: Result<Duration, ProbeError>) {
+        if let Some(state) = self.inner.provider_states.lock().unwrap().get_mut(url) {
+            state.record_passive(outcome);
+        }
+    }
 
-            // Select between different branches based on received messages or timeouts.
+    /// Returns the rolling statistics of every provider probed at least once.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     balancer.wait_until_ready().await;
+    ///     for (url, stats) in balancer.provider_stats() {
+    ///         println!("{}: p90 {:?}, success rate {}", url, stats.p90, stats.success_rate);
+    ///     }
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    pub fn provider_stats(&self) -> HashMap<String, ProviderStats> {
+        self.inner
+            .provider_states
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, state)| (url.clone(), state.stats()))
+            .collect()
+    }
 
-            tokio::select! {
-                // Handle a message from the receiver indicating destruction.
-                _ = receiver_clone.changed() => {
-                    break;
-                }
+    /// Returns the URL of the fastest provider, or an error if no provider qualifies.
+    ///
+    /// Unlike `get_fastest_provider`, this does not panic when every provider is down or quarantined, before
+    /// the first probes completed, or after the balancer was destroyed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use web3_closest_provider::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, SelectorError};
+    /// use std::time::Duration;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let providers = vec!["https://rpc.ankr.com/eth".to_string()];
+    ///     let balancer = ClosestWeb3RpcProviderSelector::init(providers, Duration::from_secs(10));
+    ///
+    ///     match balancer.try_get_fastest_provider() {
+    ///         Ok(url) => println!("Fastest provider: {}", url),
+    ///         Err(SelectorError::NotReady) => println!("Balancer is not ready yet"),
+    ///         Err(e) => eprintln!("{}", e),
+    ///     }
+    ///     balancer.destroy();
+    /// }
+    /// ```
+    pub fn try_get_fastest_provider(&self) -> Result<ProviderUrl, SelectorError> {
+        if self.inner.destroyed.load(Ordering::SeqCst) {
+            return Err(SelectorError::Destroyed);
+        }
+        if let Some(url) = self.select_provider() {
+            return Ok(url);
+        }
 
-                // Perform a request to one of the URLs concurrently.
-                _ = async {
-                    for url in &urls {
-                        let response = Self::perform_web3_client_version_request(&url).await;
-                        let response_time = response.unwrap_or(u128::MAX);
+        // Find out why no provider qualifies.
+        let down: Vec<(ProviderUrl, ProbeError)> = self
+            .inner
+            .provider_states
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| {
+                state.is_down()
+                    || !state.has_answered()
+                    || state.circuit() != CircuitState::Closed
+                    || state.cooldown().is_some()
+            })
+            .filter_map(|(url, state)| {
+                state
+                    .stats()
+                    .last_error
+                    .map(|last_error| (url.clone(), last_error))
+            })
+            .collect();
+        let mismatches = self.quarantined_providers();
 
-                        // Acquire a lock on the response time map and update the value.
-                        let mut response_times_map = response_times.lock().unwrap();
-                        response_times_map.insert(url.clone(), response_time);
-                        drop(response_times_map);
-                    }
-                } => {}
+        if !down.is_empty() {
+            Err(SelectorError::AllProvidersUnhealthy { down })
+        } else if !mismatches.is_empty() {
+            Err(SelectorError::ChainIdMismatch(mismatches))
+        } else {
+            Err(SelectorError::NotReady)
+        }
+    }
+
+    /// Returns the providers quarantined because they reported an unexpected chain ID.
+    pub fn quarantined_providers(&self) -> Vec<ChainIdMismatch> {
+        let Some(expected) = self.inner.config.expected_chain_id else {
+            return Vec::new();
+        };
+
+        self.inner
+            .provider_states
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(url, state)| match state.chain_id {
+                Some(actual) if actual != expected => Some(ChainIdMismatch {
+                    url: url.clone(),
+                    expected,
+                    actual,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
 
-                // Wait for the interval duration to pass.
-                _ = sleep(checking_interval) => {}
+    /// Waits until the balancer is ready, checking it again whenever a round of probes completes.
+    ///
+    /// Returns `SelectorError::Destroyed` if the balancer is destroyed before it is ready.
+    async fn wait_for_readiness(&self) -> Result<(), SelectorError> {
+        let mut rounds = self.inner.rounds.clone();
+        loop {
+            if self.inner.destroyed.load(Ordering::SeqCst) {
+                return Err(SelectorError::Destroyed);
+            }
+            if self.is_ready() {
+                return Ok(());
+            }
+
+            // The prober drops the sender once it stops.
+            if rounds.changed().await.is_err() {
+                return Err(SelectorError::Destroyed);
             }
         }
     }
 
-    /// Sends a JSON-RPC request to a given URL and returns the response time or an error.
-    async fn perform_web3_client_version_request(url: &str) -> Result<u128, LibError> {
-        let client = reqwest::Client::new();
-
-        // Prepare the JSON-RPC request body.
-        let body = serde_json::json!({
-            "jsonrpc": "2.0",
-            "method": "web3_clientVersion",
-            "params": [],
-            "id": 1
-        });
+    /// Selects a provider with the configured strategy, `None` if no provider is eligible.
+    ///
+    /// When stickiness is enabled, the current provider is kept as long as it remains eligible.
+    fn select_provider(&self) -> Option<String> {
+        let provider_states = self.inner.provider_states.lock().unwrap();
+        let ranking = Ranking::new(&provider_states, &self.inner.config);
+        self.select_from(&ranking)
+    }
 
-        // Record the start time of the request.
-        let start_time = Instant::now();
+    /// Returns the URLs of the providers a request is sent to in order, starting with the selected provider.
+    fn failover_order(&self) -> Vec<String> {
+        let provider_states = self.inner.provider_states.lock().unwrap();
+        let ranking = Ranking::new(&provider_states, &self.inner.config);
+        match self.select_from(&ranking) {
+            Some(first) => ranking.failover_order(&first),
+            None => Vec::new(),
+        }
+    }
 
-        // Send the request and handle potential errors.
-        let response = client
-            .post(url)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| LibError {
-                message: format!("Failed to send request: {:?}", e),
-            })?;
-
-        // Record the end time of the request.
-        let end_time = Instant::now();
-
-        // Check if the response contains an error field.
-        let json_response: JsonRpcResponse = response.json().await.map_err(|e| LibError {
-            message: format!("Failed to parse response: {:?}", e),
-        })?;
-
-        if let Some(error) = json_response.error {
-            return Err(LibError {
-                message: format!("Received error response: {:?}", error),
-            });
+    /// Selects a provider of `ranking` with the configured strategy, `None` if no provider is eligible.
+    fn select_from(&self, ranking: &Ranking) -> Option<String> {
+        // Keep the current provider if it is still eligible.
+        if self.inner.config.stickiness.is_some() {
+            let selection = self.inner.selection.lock().unwrap();
+            if let Some(current) = selection.current() {
+                if ranking.contains(current) {
+                    return Some(current.to_string());
+                }
+            }
         }
 
-        // Calculate and return the response time.
-        Ok(end_time.duration_since(start_time).as_micros())
+        ranking
+            .select(self.inner.config.strategy.as_ref())
+            .map(str::to_string)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use crate::{ClosestWeb3Provider, ClosestWeb3RpcProviderSelector};
+    use crate::{
+        mock::MockProvider, BuildError, ChainIdMismatch, CircuitBreakerPolicy, CircuitState,
+        ClosestWeb3Provider, ClosestWeb3RpcProviderSelector, HealthPolicy, LatencyMode,
+        PriorityTiers, ProbeError, ProbeSpec, ProviderHealth, ReadinessPolicy, RetryPolicy,
+        RoundRobinTopK, SelectionStatistic, SelectorConfig, SelectorError, SelectorEvent,
+        Stickiness, SwitchMargin,
+    };
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    };
     use std::time::Duration;
-    use tokio::time::sleep;
+    use tokio::{
+        net::TcpListener,
+        time::{sleep, timeout},
+    };
 
     #[tokio::test]
     async fn test_init() {
-        let urls = vec![
-            "https://eth.llamarpc.com".to_string(),
-            "https://eth.llamarpc.com".to_string(),
-        ];
+        let url = MockProvider::new().spawn().await;
+        let urls = vec![url.clone(), url.clone()];
         let provider = ClosestWeb3RpcProviderSelector::init(urls.clone(), Duration::from_secs(10));
         assert_eq!(provider.is_ready(), false);
         provider.wait_until_ready().await;
         assert_eq!(provider.is_ready(), true);
-        assert_eq!(
-            provider.get_fastest_provider(),
-            "https://eth.llamarpc.com".to_string()
-        );
+        assert_eq!(provider.get_fastest_provider(), url);
     }
 
     #[tokio::test]
     async fn test_destroy() {
-        let urls = vec![
-            "https://eth.llamarpc.com".to_string(),
-            "https://eth.llamarpc.com".to_string(),
-        ];
+        let url = MockProvider::new().spawn().await;
+        let urls = vec![url.clone(), url.clone()];
         let provider = ClosestWeb3RpcProviderSelector::init(urls.clone(), Duration::from_secs(10));
         // Check that the interval handle was created successfully
         assert_eq!(provider.is_ready(), false);
         provider.wait_until_ready().await;
         assert_eq!(provider.is_ready(), true);
-        assert_eq!(
-            provider.get_fastest_provider(),
-            "https://eth.llamarpc.com".to_string()
-        );
+        assert_eq!(provider.get_fastest_provider(), url);
 
         // Destroy the provider
         provider.destroy();
@@ -388,19 +929,14 @@ mod tests {
     #[tokio::test]
     #[should_panic]
     async fn test_destroy_and_panic_after_reading_provider_from_destroyed_instance() {
-        let urls = vec![
-            "https://eth.llamarpc.com".to_string(),
-            "https://eth.llamarpc.com".to_string(),
-        ];
+        let url = MockProvider::new().spawn().await;
+        let urls = vec![url.clone(), url.clone()];
         let provider = ClosestWeb3RpcProviderSelector::init(urls.clone(), Duration::from_secs(10));
         // Check that the interval handle was created successfully
         assert_eq!(provider.is_ready(), false);
         provider.wait_until_ready().await;
         assert_eq!(provider.is_ready(), true);
-        assert_eq!(
-            provider.get_fastest_provider(),
-            "https://eth.llamarpc.com".to_string()
-        );
+        assert_eq!(provider.get_fastest_provider(), url);
 
         // Destroy the provider
         provider.destroy();
@@ -411,21 +947,15 @@ mod tests {
 
     #[tokio::test]
     async fn test_provider_with_multiple_requests() {
-        let urls: Vec<String> = vec![
-            "https://eth.llamarpc.com",
-            "https://rpc.lokibuilder.xyz/wallet",
-            "wss://ethereum.publicnode.com",
-            "wss://mainnet.gateway.tenderly.co",
-            "https://gateway.tenderly.co/public/mainnet",
-            "https://core.gashawk.io/rpc",
-            "https://mainnet.gateway.tenderly.co",
-            "https://virginia.rpc.blxrbdn.com",
-            "https://uk.rpc.blxrbdn.com",
-            "https://singapore.rpc.blxrbdn.com",
-        ]
-        .iter()
-        .map(|&s| s.to_string())
-        .collect();
+        let mut urls = Vec::new();
+        for delay in [30, 10, 50, 20, 40] {
+            let provider = MockProvider::new().delay(Duration::from_millis(delay));
+            urls.push(provider.spawn().await);
+        }
+        for delay in [25, 15, 35, 45, 5] {
+            let provider = MockProvider::new().delay(Duration::from_millis(delay));
+            urls.push(provider.spawn_ws().await);
+        }
         let provider = ClosestWeb3RpcProviderSelector::init(urls.clone(), Duration::from_secs(2));
         provider.wait_until_ready().await;
         assert_eq!(provider.is_ready(), true);
@@ -439,4 +969,1231 @@ mod tests {
         sleep(Duration::from_millis(1000)).await;
         assert_eq!(provider.is_ready(), false);
     }
+
+    #[tokio::test]
+    #[ignore = "Sends requests to a public HTTPS endpoint"]
+    async fn test_live_https_provider() {
+        let url = "https://eth.llamarpc.com".to_string();
+        let provider =
+            ClosestWeb3RpcProviderSelector::init(vec![url.clone()], Duration::from_secs(10));
+        timeout(Duration::from_secs(30), provider.wait_until_ready())
+            .await
+            .unwrap();
+        assert_eq!(provider.get_fastest_provider(), url);
+
+        let response = provider
+            .request("web3_clientVersion", json!([]))
+            .await
+            .unwrap();
+        assert_eq!(response.provider, url);
+        assert!(response.result.is_string());
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    #[ignore = "Sends requests to a public WebSocket endpoint"]
+    async fn test_live_websocket_provider() {
+        let url = "wss://ethereum.publicnode.com".to_string();
+        let provider =
+            ClosestWeb3RpcProviderSelector::init(vec![url.clone()], Duration::from_secs(10));
+        timeout(Duration::from_secs(30), provider.wait_until_ready())
+            .await
+            .unwrap();
+        assert_eq!(provider.get_fastest_provider(), url);
+
+        let response = provider
+            .request("web3_clientVersion", json!([]))
+            .await
+            .unwrap();
+        assert_eq!(response.provider, url);
+        assert!(response.result.is_string());
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_all_providers_are_probed_concurrently() {
+        let mut urls = Vec::new();
+        for _ in 0..12 {
+            urls.push(
+                MockProvider::new()
+                    .delay(Duration::from_millis(300))
+                    .spawn()
+                    .await,
+            );
+        }
+        let provider = ClosestWeb3RpcProviderSelector::init(urls.clone(), Duration::from_secs(1));
+        provider.wait_until_ready().await;
+
+        // The first round is recorded at once and contains a fresh sample for every provider.
+        let stats = provider.provider_stats();
+        assert_eq!(stats.len(), urls.len());
+        assert!(stats.values().all(|stats| stats.last.is_some()));
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_hanging_provider_does_not_stall_the_round() {
+        let fast_url = MockProvider::new().spawn().await;
+        let hanging_url = MockProvider::new()
+            .delay(Duration::from_secs(60))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::init(
+            vec![hanging_url.clone(), fast_url.clone()],
+            Duration::from_millis(200),
+        );
+        provider.wait_until_ready().await;
+
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&hanging_url].last, None);
+        assert!(stats[&fast_url].last.is_some());
+        assert_eq!(provider.get_fastest_provider(), fast_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_probe_result_is_validated_against_spec() {
+        let node_url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .handler(|method, _| match method {
+                "eth_blockNumber" => json!("0x10d4f"),
+                _ => json!(null),
+            })
+            .spawn()
+            .await;
+        let cached_gateway_url = MockProvider::new().spawn().await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![node_url.clone(), cached_gateway_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                probe: ProbeSpec::block_number(),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // The gateway answers faster but with a result that does not match the probe.
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&cached_gateway_url].last, None);
+        assert_eq!(provider.get_fastest_provider(), node_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_lagging_provider_is_ranked_last() {
+        let synced_url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .handler(|method, _| match method {
+                "eth_blockNumber" => json!("0x64"),
+                _ => json!("synced/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let lagging_url = MockProvider::new()
+            .handler(|method, _| match method {
+                "eth_blockNumber" => json!("0x55"),
+                _ => json!("lagging/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![synced_url.clone(), lagging_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                max_block_lag: Some(10),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // The lagging provider answers faster but is 15 blocks behind the head.
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&synced_url].block_number, Some(100));
+        assert_eq!(stats[&lagging_url].block_number, Some(85));
+        assert!(stats[&lagging_url].last < stats[&synced_url].last);
+        assert_eq!(provider.get_fastest_provider(), synced_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_block_lag_ignores_providers_on_other_chains() {
+        let synced_url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .handler(|method, _| match method {
+                "eth_chainId" => json!("0x1"),
+                "eth_blockNumber" => json!("0x64"),
+                _ => json!("synced/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let lagging_url = MockProvider::new()
+            .handler(|method, _| match method {
+                "eth_chainId" => json!("0x1"),
+                "eth_blockNumber" => json!("0x55"),
+                _ => json!("lagging/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let polygon_url = MockProvider::new()
+            .handler(|method, _| match method {
+                "eth_chainId" => json!("0x89"),
+                "eth_blockNumber" => json!("0x3000000"),
+                _ => json!("polygon/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![synced_url.clone(), lagging_url.clone(), polygon_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                max_block_lag: Some(10),
+                expected_chain_id: Some(1),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // The chain head is taken from the mainnet providers only, so the synced provider is not lagging.
+        let stats = provider.provider_stats();
+        assert!(stats[&polygon_url].block_number > stats[&synced_url].block_number);
+        assert!(stats[&lagging_url].last < stats[&synced_url].last);
+        assert_eq!(provider.get_fastest_provider(), synced_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_provider_on_wrong_chain_is_quarantined() {
+        let mainnet_url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .handler(|method, _| match method {
+                "eth_chainId" => json!("0x1"),
+                _ => json!("mainnet/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let sepolia_url = MockProvider::new()
+            .handler(|method, _| match method {
+                "eth_chainId" => json!("0xaa36a7"),
+                _ => json!("sepolia/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![mainnet_url.clone(), sepolia_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                expected_chain_id: Some(1),
+                ..Default::default()
+            },
+        );
+        let mut events = provider.subscribe();
+        provider.wait_until_ready().await;
+
+        // The Sepolia provider answers faster but is never selected.
+        let mismatch = ChainIdMismatch {
+            url: sepolia_url.clone(),
+            expected: 1,
+            actual: 11155111,
+        };
+        assert_eq!(provider.get_fastest_provider(), mainnet_url);
+        assert_eq!(provider.quarantined_providers(), vec![mismatch.clone()]);
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SelectorEvent::ChainIdMismatch(mismatch)
+        );
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_provider() {
+        let http_url = MockProvider::new()
+            .delay(Duration::from_millis(100))
+            .spawn()
+            .await;
+        let ws_url = MockProvider::new().spawn_ws().await;
+        let provider = ClosestWeb3RpcProviderSelector::init(
+            vec![http_url.clone(), ws_url.clone()],
+            Duration::from_secs(1),
+        );
+        provider.wait_until_ready().await;
+
+        let stats = provider.provider_stats();
+        assert!(stats[&http_url].last.is_some());
+        assert!(stats[&ws_url].last.is_some());
+        assert_eq!(provider.get_fastest_provider(), ws_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_provider_reconnects() {
+        let ws_url = MockProvider::new().close_after(1).spawn_ws().await;
+        let provider =
+            ClosestWeb3RpcProviderSelector::init(vec![ws_url.clone()], Duration::from_millis(300));
+        provider.wait_until_ready().await;
+
+        // Every round is answered on a new connection after the previous one was closed.
+        for _ in 0..3 {
+            sleep(Duration::from_millis(300)).await;
+            let stats = provider.provider_stats();
+            assert!(stats[&ws_url].last.is_some());
+        }
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_stalled_websocket_provider_reconnects() {
+        let ws_url = MockProvider::new().stall_after(1).spawn_ws().await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![ws_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(100),
+                probe_timeout: Duration::from_millis(100),
+                health: HealthPolicy {
+                    failures_before_down: 10,
+                    ..Default::default()
+                },
+                retry: RetryPolicy {
+                    attempt_timeout: Duration::from_millis(100),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // Connections that stop answering are re-established, so probes keep succeeding now and then.
+        sleep(Duration::from_millis(1500)).await;
+        let stats = provider.provider_stats()[&ws_url].clone();
+        let successes = (stats.success_rate * stats.samples as f64).round() as usize;
+        assert!(successes > 1);
+        provider.destroy();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_ipc_provider() {
+        let http_url = MockProvider::new()
+            .delay(Duration::from_millis(100))
+            .spawn()
+            .await;
+        let ipc_path = MockProvider::new().spawn_ipc().await;
+        let ipc_url = format!("ipc://{}", MockProvider::new().spawn_ipc().await);
+        let provider = ClosestWeb3RpcProviderSelector::init(
+            vec![http_url.clone(), ipc_path.clone(), ipc_url.clone()],
+            Duration::from_secs(1),
+        );
+        provider.wait_until_ready().await;
+
+        // Both plain socket paths and ipc:// URLs are probed alongside remote providers.
+        let stats = provider.provider_stats();
+        assert!(stats[&http_url].last.is_some());
+        assert!(stats[&ipc_path].last.is_some());
+        assert!(stats[&ipc_url].last.is_some());
+        assert_ne!(provider.get_fastest_provider(), http_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_provider_stats_are_rolling() {
+        let url = MockProvider::new()
+            .delay(Duration::from_millis(10))
+            .spawn()
+            .await;
+        let provider =
+            ClosestWeb3RpcProviderSelector::init(vec![url.clone()], Duration::from_millis(200));
+        provider.wait_until_ready().await;
+        sleep(Duration::from_millis(700)).await;
+
+        let stats = provider.provider_stats()[&url].clone();
+        assert!(stats.samples >= 3);
+        assert_eq!(stats.success_rate, 1.0);
+        assert!(stats.ewma.unwrap() >= Duration::from_millis(10));
+        assert!(stats.p50 <= stats.p90 && stats.p90 <= stats.p95 && stats.p95 <= stats.p99);
+        assert!(stats.jitter.is_some());
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_selection_strategy() {
+        let fast_url = MockProvider::new().spawn().await;
+        let slow_url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .spawn()
+            .await;
+        let urls = vec![fast_url.clone(), slow_url.clone()];
+
+        // Priority tiers prefer the slower provider as long as it answers.
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            urls.clone(),
+            SelectorConfig {
+                strategy: Arc::new(PriorityTiers::new(vec![vec![slow_url.clone()]])),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+        assert_eq!(provider.get_fastest_provider(), slow_url);
+        provider.destroy();
+
+        // Round robin over the two fastest providers alternates between them.
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            urls,
+            SelectorConfig {
+                strategy: Arc::new(RoundRobinTopK::new(2)),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(provider.get_fastest_provider(), fast_url);
+        assert_eq!(provider.get_fastest_provider(), slow_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_sticky_selection() {
+        let steady_url = MockProvider::new()
+            .delay(Duration::from_millis(30))
+            .spawn()
+            .await;
+        let improving_url = MockProvider::new()
+            .delays(|request| match request {
+                0 => Duration::from_millis(60),
+                _ => Duration::ZERO,
+            })
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![steady_url.clone(), improving_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                selection_statistic: SelectionStatistic::Last,
+                stickiness: Some(Stickiness::new(
+                    SwitchMargin::Absolute(Duration::from_millis(10)),
+                    3,
+                )),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // The improving provider has been faster for a single round, so the first selection is kept.
+        sleep(Duration::from_millis(250)).await;
+        assert!(provider.provider_stats()[&improving_url].last < Some(Duration::from_millis(10)));
+        assert_eq!(provider.get_fastest_provider(), steady_url);
+
+        // After three consecutive faster rounds, the improving provider is selected.
+        sleep(Duration::from_millis(450)).await;
+        assert_eq!(provider.get_fastest_provider(), improving_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_sticky_selection_follows_strategy() {
+        let preferred_url = MockProvider::new()
+            .delay(Duration::from_millis(40))
+            .spawn()
+            .await;
+        let fast_url = MockProvider::new().spawn().await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![preferred_url.clone(), fast_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(100),
+                strategy: Arc::new(PriorityTiers::new(vec![vec![preferred_url.clone()]])),
+                stickiness: Some(Stickiness::new(
+                    SwitchMargin::Absolute(Duration::from_millis(10)),
+                    2,
+                )),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // The provider preferred by the strategy is kept although another one is faster.
+        sleep(Duration::from_millis(400)).await;
+        assert!(
+            provider.provider_stats()[&fast_url].ewma
+                < provider.provider_stats()[&preferred_url].ewma
+        );
+        assert_eq!(provider.get_fastest_provider(), preferred_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_failing_provider_is_excluded() {
+        let healthy_url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .spawn()
+            .await;
+        let answered = Arc::new(AtomicUsize::new(0));
+        let failing_url = MockProvider::new()
+            .handler(move |_, _| match answered.fetch_add(1, Ordering::Relaxed) {
+                0 => json!("failing/v1.0.0"),
+                _ => json!(null),
+            })
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![healthy_url.clone(), failing_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+        assert_eq!(provider.get_fastest_provider(), failing_url);
+
+        // The failing provider is degraded after its first failure and down after the second one.
+        sleep(Duration::from_millis(250)).await;
+        assert!(matches!(
+            provider.provider_stats()[&failing_url].health,
+            ProviderHealth::Degraded { .. }
+        ));
+        assert_eq!(provider.get_fastest_provider(), healthy_url);
+        sleep(Duration::from_millis(200)).await;
+        let stats = provider.provider_stats();
+        assert!(matches!(
+            stats[&failing_url].health,
+            ProviderHealth::Down { .. }
+        ));
+        assert_eq!(stats[&healthy_url].health, ProviderHealth::Healthy);
+        assert_eq!(provider.get_fastest_provider(), healthy_url);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_no_healthy_provider() {
+        let url = MockProvider::new()
+            .handler(|_, _| json!(null))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            provider.try_get_fastest_provider(),
+            Err(SelectorError::NotReady)
+        );
+
+        // Once down, the only provider is not returned anymore.
+        sleep(Duration::from_millis(600)).await;
+        assert!(!provider.is_ready());
+        let Err(SelectorError::AllProvidersUnhealthy { down }) =
+            provider.try_get_fastest_provider()
+        else {
+            panic!("Expected every provider to be unhealthy");
+        };
+        assert_eq!(down.len(), 1);
+        assert_eq!(down[0].0, url);
+        assert_eq!(down[0].1, ProbeError::UnexpectedResult("null".to_string()));
+
+        provider.destroy();
+        assert_eq!(
+            provider.try_get_fastest_provider(),
+            Err(SelectorError::Destroyed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_that_never_answered_is_not_selected() {
+        let mut refused_urls = Vec::new();
+        for _ in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            refused_urls.push(format!("http://{}", listener.local_addr().unwrap()));
+        }
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            refused_urls.clone(),
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                ..Default::default()
+            },
+        );
+        sleep(Duration::from_millis(300)).await;
+
+        // Both providers are only degraded after their first failure, but neither of them ever answered.
+        let stats = provider.provider_stats();
+        for url in &refused_urls {
+            assert!(matches!(
+                stats[url].health,
+                ProviderHealth::Degraded {
+                    last_error: ProbeError::ConnectRefused(_)
+                }
+            ));
+        }
+        let Err(SelectorError::AllProvidersUnhealthy { mut down }) =
+            provider.try_get_fastest_provider()
+        else {
+            panic!("Expected every provider to be unhealthy");
+        };
+        down.sort_by(|a, b| a.0.cmp(&b.0));
+        refused_urls.sort();
+        assert_eq!(
+            down.into_iter().map(|(url, _)| url).collect::<Vec<_>>(),
+            refused_urls
+        );
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_every_provider_on_wrong_chain() {
+        let sepolia_url = MockProvider::new()
+            .handler(|method, _| match method {
+                "eth_chainId" => json!("0xaa36a7"),
+                _ => json!("sepolia/v1.0.0"),
+            })
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![sepolia_url.clone()],
+            SelectorConfig {
+                expected_chain_id: Some(1),
+                ..Default::default()
+            },
+        );
+        sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            provider.try_get_fastest_provider(),
+            Err(SelectorError::ChainIdMismatch(vec![ChainIdMismatch {
+                url: sepolia_url,
+                expected: 1,
+                actual: 11155111,
+            }]))
+        );
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_probe_errors_are_classified() {
+        let server_error_url = MockProvider::new().status(500).spawn().await;
+        let rate_limited_url = MockProvider::new()
+            .status(429)
+            .response_header("Retry-After", "120")
+            .spawn()
+            .await;
+        let malformed_url = MockProvider::new().body("not json").spawn().await;
+        let json_rpc_error_url = MockProvider::new()
+            .handler(|_, _| json!({ "error": { "code": -32601, "message": "Method not found" } }))
+            .spawn()
+            .await;
+        let refused_url = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![
+                server_error_url.clone(),
+                rate_limited_url.clone(),
+                malformed_url.clone(),
+                json_rpc_error_url.clone(),
+                refused_url.clone(),
+            ],
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                ..Default::default()
+            },
+        );
+        sleep(Duration::from_millis(500)).await;
+
+        let stats = provider.provider_stats();
+        assert_eq!(
+            stats[&server_error_url].last_error,
+            Some(ProbeError::HttpStatus(500))
+        );
+        assert_eq!(
+            stats[&rate_limited_url].last_error,
+            Some(ProbeError::RateLimited {
+                retry_after: Some(Duration::from_secs(120))
+            })
+        );
+        assert!(stats[&rate_limited_url].cooldown.is_some());
+        assert!(matches!(
+            stats[&malformed_url].last_error,
+            Some(ProbeError::MalformedJson(_))
+        ));
+        assert_eq!(
+            stats[&json_rpc_error_url].last_error,
+            Some(ProbeError::JsonRpc {
+                code: -32601,
+                message: "Method not found".to_string()
+            })
+        );
+        assert!(matches!(
+            stats[&refused_url].last_error,
+            Some(ProbeError::ConnectRefused(_))
+        ));
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_probe_is_penalized() {
+        let fast_url = MockProvider::new().spawn().await;
+        let slow_url = MockProvider::new()
+            .delay(Duration::from_millis(500))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![fast_url.clone(), slow_url.clone()],
+            SelectorConfig {
+                probe_timeout: Duration::from_millis(100),
+                ..Default::default()
+            },
+        );
+        provider.wait_until_ready().await;
+
+        // A single timeout takes the slow provider down with the default timeout penalty.
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&fast_url].health, ProviderHealth::Healthy);
+        assert_eq!(
+            stats[&slow_url].health,
+            ProviderHealth::Down {
+                last_error: ProbeError::Timeout
+            }
+        );
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_connections_are_kept_warm() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let url = MockProvider::new()
+            .count_connections(connections.clone())
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(100),
+                http_client: Some(reqwest::Client::new()),
+                ..Default::default()
+            },
+        );
+        sleep(Duration::from_millis(550)).await;
+
+        // Every round reuses the connection opened by the first one.
+        assert!(provider.provider_stats()[&url].samples > 1);
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+        assert_eq!(provider.provider_stats()[&url].cold_latency, None);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_cold_and_warm_latency() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let url = MockProvider::new()
+            .count_connections(connections.clone())
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(100),
+                latency_mode: LatencyMode::ColdAndWarm,
+                ..Default::default()
+            },
+        );
+        sleep(Duration::from_millis(550)).await;
+
+        // The warm connection is reused while every cold probe opens its own connection.
+        let stats = provider.provider_stats();
+        assert!(stats[&url].cold_latency.is_some());
+        assert_eq!(connections.load(Ordering::Relaxed), stats[&url].samples + 1);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_latency_phases() {
+        let url = MockProvider::new()
+            .delay(Duration::from_millis(50))
+            .spawn()
+            .await;
+        let cold = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                latency_mode: LatencyMode::Cold,
+                ..Default::default()
+            },
+        );
+        let warm = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                ..Default::default()
+            },
+        );
+        sleep(Duration::from_millis(500)).await;
+
+        // New connections to an IP address skip the DNS lookup and, over plain HTTP, the TLS handshake.
+        let phases = cold.provider_stats()[&url].phases.unwrap();
+        assert!(phases.connect.is_some());
+        assert_eq!(phases.dns, None);
+        assert_eq!(phases.tls, None);
+        assert!(phases.ttfb >= Duration::from_millis(50));
+
+        // Warm probes only report the request phases.
+        let phases = warm.provider_stats()[&url].phases.unwrap();
+        assert_eq!(phases.connect, None);
+        assert!(phases.ttfb >= Duration::from_millis(50));
+
+        cold.destroy();
+        warm.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_ready_timeout() {
+        let url = MockProvider::new().spawn().await;
+        let failing_url = MockProvider::new()
+            .handler(|_, _| json!(null))
+            .spawn()
+            .await;
+
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                readiness: ReadinessPolicy::AllProbedOnce,
+                ..Default::default()
+            },
+        );
+        assert!(!provider.is_ready());
+        assert_eq!(
+            provider
+                .wait_until_ready_timeout(Duration::from_secs(2))
+                .await,
+            Ok(())
+        );
+        assert!(provider.is_ready());
+        provider.destroy();
+
+        // A single available provider does not satisfy a policy requiring two.
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![url.clone(), failing_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                readiness: ReadinessPolicy::AtLeast(2),
+                health: HealthPolicy {
+                    failures_before_down: 1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            provider
+                .wait_until_ready_timeout(Duration::from_millis(600))
+                .await,
+            Err(SelectorError::NotReady)
+        );
+        assert_eq!(provider.get_fastest_provider(), url);
+        provider.destroy();
+
+        // Waiting for unreachable providers reports why none of them qualifies.
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![failing_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_millis(200),
+                health: HealthPolicy {
+                    failures_before_down: 1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let Err(SelectorError::AllProvidersUnhealthy { down }) = provider
+            .wait_until_ready_timeout(Duration::from_secs(1))
+            .await
+        else {
+            panic!("Expected every provider to be unhealthy");
+        };
+        assert_eq!(down[0].0, failing_url);
+
+        // Providers that never answered do not make the balancer ready, even before they are down.
+        let refused_url = {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        let unreachable = ClosestWeb3RpcProviderSelector::with_config(
+            vec![refused_url.clone()],
+            SelectorConfig {
+                checking_interval: Duration::from_secs(1),
+                readiness: ReadinessPolicy::AtLeast(1),
+                ..Default::default()
+            },
+        );
+        let Err(SelectorError::AllProvidersUnhealthy { down }) = unreachable
+            .wait_until_ready_timeout(Duration::from_millis(500))
+            .await
+        else {
+            panic!("Expected every provider to be unhealthy");
+        };
+        assert_eq!(down[0].0, refused_url);
+        assert!(!unreachable.is_ready());
+        unreachable.destroy();
+
+        // Destroying the balancer wakes up the tasks waiting for it.
+        let provider = Arc::new(provider);
+        let waiter = provider.clone();
+        let wait = tokio::spawn(async move {
+            waiter
+                .wait_until_ready_timeout(Duration::from_secs(5))
+                .await
+        });
+        sleep(Duration::from_millis(50)).await;
+        provider.destroy();
+        assert_eq!(wait.await.unwrap(), Err(SelectorError::Destroyed));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown() {
+        let url = MockProvider::new().spawn().await;
+        let provider =
+            ClosestWeb3RpcProviderSelector::init(vec![url.clone()], Duration::from_secs(10));
+        provider.wait_until_ready().await;
+
+        // Destroying twice has no effect and the background task stops.
+        provider.destroy();
+        provider.destroy();
+        timeout(Duration::from_secs(1), provider.shutdown())
+            .await
+            .unwrap();
+
+        // Dropping the balancer stops its background task too.
+        let provider = ClosestWeb3RpcProviderSelector::init(vec![url], Duration::from_millis(100));
+        provider.wait_until_ready().await;
+        let stopped = provider.stopped();
+        drop(provider);
+        timeout(Duration::from_secs(1), stopped).await.unwrap();
+    }
+
+    #[test]
+    fn test_selector_is_shareable() {
+        // Clones of the balancer are moved across tasks, so it must remain `Send + Sync + 'static`.
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<ClosestWeb3RpcProviderSelector>();
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_the_balancer() {
+        let url = MockProvider::new().spawn().await;
+        let provider =
+            ClosestWeb3RpcProviderSelector::init(vec![url.clone()], Duration::from_millis(100));
+        let clone = provider.clone();
+
+        // Clones share the background task and statistics.
+        let task = tokio::spawn(async move {
+            clone.wait_until_ready().await;
+            clone
+        });
+        let clone = task.await.unwrap();
+        assert!(provider.is_ready());
+        assert_eq!(provider.provider_stats().len(), 1);
+
+        // Dropping a clone keeps the balancer running until the last one is dropped.
+        let stopped = provider.stopped();
+        drop(provider);
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(clone.get_fastest_provider(), url);
+        drop(clone);
+        timeout(Duration::from_secs(1), stopped).await.unwrap();
+
+        // Destroying any clone stops the balancer for all of them.
+        let provider = ClosestWeb3RpcProviderSelector::init(vec![url], Duration::from_millis(100));
+        let clone = provider.clone();
+        provider.wait_until_ready().await;
+        clone.destroy();
+        assert_eq!(
+            provider.try_get_fastest_provider(),
+            Err(SelectorError::Destroyed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_builder_sends_headers() {
+        let url = MockProvider::new()
+            .require_header("x-api-key", "secret")
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::builder()
+            .provider(url.clone())
+            .header("X-Api-Key", "secret")
+            .checking_interval(Duration::from_millis(100))
+            .latency_mode(LatencyMode::ColdAndWarm)
+            .build()
+            .unwrap();
+        let unauthorized = ClosestWeb3RpcProviderSelector::builder()
+            .provider(url.clone())
+            .checking_interval(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        sleep(Duration::from_millis(350)).await;
+
+        // Both warm and cold probes carry the headers.
+        let stats = &provider.provider_stats()[&url];
+        assert_eq!(stats.health, ProviderHealth::Healthy);
+        assert!(stats.cold_latency.is_some());
+        assert_eq!(
+            unauthorized.provider_stats()[&url].last_error,
+            Some(ProbeError::HttpStatus(401))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_providers() {
+        let slow_url = MockProvider::new()
+            .delay(Duration::from_millis(30))
+            .spawn()
+            .await;
+        let fast_url = MockProvider::new().spawn().await;
+        let other_url = MockProvider::new()
+            .delay(Duration::from_millis(60))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::init(
+            vec![slow_url.clone()],
+            Duration::from_millis(200),
+        );
+        let mut events = provider.subscribe();
+        provider.wait_until_ready().await;
+
+        // Added providers are probed from the next round on.
+        provider.add_provider(fast_url.clone()).unwrap();
+        assert_eq!(
+            provider.add_provider(fast_url.clone()),
+            Err(BuildError::DuplicateProvider(fast_url.clone()))
+        );
+        assert!(matches!(
+            provider.add_provider("ftp://a.example"),
+            Err(BuildError::InvalidUrl { .. })
+        ));
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SelectorEvent::ProviderAdded(fast_url.clone())
+        );
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(provider.get_fastest_provider(), fast_url);
+
+        // Replacing the providers keeps the statistics of the unchanged ones.
+        let samples = provider.provider_stats()[&slow_url].samples;
+        provider
+            .replace_providers(vec![slow_url.clone(), other_url.clone()])
+            .unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SelectorEvent::ProviderRemoved(fast_url.clone())
+        );
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SelectorEvent::ProviderAdded(other_url.clone())
+        );
+        assert_eq!(provider.get_fastest_provider(), slow_url);
+        assert!(!provider.provider_stats().contains_key(&fast_url));
+        assert!(provider.provider_stats()[&slow_url].samples >= samples);
+        sleep(Duration::from_millis(500)).await;
+        assert!(provider.provider_stats().contains_key(&other_url));
+        assert!(!provider.provider_stats().contains_key(&fast_url));
+
+        // Removed providers are no longer selected.
+        assert!(provider.remove_provider(&slow_url).unwrap());
+        assert!(!provider.remove_provider(&slow_url).unwrap());
+        assert_eq!(
+            events.recv().await.unwrap(),
+            SelectorEvent::ProviderRemoved(slow_url.clone())
+        );
+        assert_eq!(provider.get_fastest_provider(), other_url);
+        assert_eq!(provider.providers(), vec![other_url.clone()]);
+
+        // The last provider cannot be removed.
+        assert!(matches!(
+            provider.remove_provider(&other_url),
+            Err(BuildError::NoProviders)
+        ));
+        assert_eq!(provider.providers(), vec![other_url]);
+    }
+
+    #[tokio::test]
+    async fn test_provider_changes_keep_the_readiness_policy_satisfiable() {
+        let first_url = MockProvider::new().spawn().await;
+        let second_url = MockProvider::new().spawn().await;
+        let provider = ClosestWeb3RpcProviderSelector::with_config(
+            vec![first_url.clone(), second_url.clone()],
+            SelectorConfig {
+                readiness: ReadinessPolicy::AtLeast(2),
+                ..Default::default()
+            },
+        );
+
+        // Changes leaving fewer providers than the policy requires are refused.
+        assert!(matches!(
+            provider.remove_provider(&second_url),
+            Err(BuildError::InvalidOption {
+                option: "readiness",
+                ..
+            })
+        ));
+        assert!(matches!(
+            provider.replace_providers(vec![first_url.clone()]),
+            Err(BuildError::InvalidOption {
+                option: "readiness",
+                ..
+            })
+        ));
+        assert_eq!(provider.providers(), vec![first_url, second_url]);
+        provider.destroy();
+    }
+
+    #[tokio::test]
+    async fn test_report_outcome() {
+        let fast_url = MockProvider::new().spawn().await;
+        let slow_url = MockProvider::new()
+            .delay(Duration::from_millis(30))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::init(
+            vec![fast_url.clone(), slow_url.clone()],
+            Duration::from_secs(10),
+        );
+        provider.wait_until_ready().await;
+        assert_eq!(provider.get_fastest_provider(), fast_url);
+
+        // Slow real requests outweigh fast probes.
+        for _ in 0..3 {
+            provider.report_outcome(&fast_url, Ok(Duration::from_millis(500)));
+        }
+        provider.report_outcome("https://unknown.example", Ok(Duration::ZERO));
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&fast_url].passive_samples, 3);
+        assert_eq!(
+            stats[&fast_url].passive_latency,
+            Some(Duration::from_millis(500))
+        );
+        assert!(!stats.contains_key("https://unknown.example"));
+        assert_eq!(provider.get_fastest_provider(), slow_url);
+
+        // Failed real requests take the provider down.
+        provider.report_outcome(&slow_url, Err(ProbeError::HttpStatus(502)));
+        provider.report_outcome(&slow_url, Err(ProbeError::HttpStatus(502)));
+        assert_eq!(
+            provider.provider_stats()[&slow_url].health,
+            ProviderHealth::Down {
+                last_error: ProbeError::HttpStatus(502)
+            }
+        );
+        assert_eq!(provider.get_fastest_provider(), fast_url);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let flaky_url = MockProvider::new().spawn().await;
+        let slow_url = MockProvider::new()
+            .delay(Duration::from_millis(30))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::builder()
+            .providers([flaky_url.clone(), slow_url.clone()])
+            .checking_interval(Duration::from_millis(100))
+            .health(HealthPolicy {
+                failures_before_down: 10,
+                ..Default::default()
+            })
+            .circuit_breaker(CircuitBreakerPolicy {
+                consecutive_failures: 2,
+                open_duration: Duration::from_millis(500),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        provider.wait_until_ready().await;
+        assert_eq!(provider.get_fastest_provider(), flaky_url);
+        assert_eq!(
+            provider.provider_stats()[&flaky_url].circuit,
+            CircuitState::Closed
+        );
+
+        // Open circuits are excluded even once probes succeed again.
+        provider.report_outcome(&flaky_url, Err(ProbeError::HttpStatus(503)));
+        provider.report_outcome(&flaky_url, Err(ProbeError::HttpStatus(503)));
+        sleep(Duration::from_millis(200)).await;
+        let stats = provider.provider_stats();
+        assert_eq!(stats[&flaky_url].health, ProviderHealth::Healthy);
+        assert_eq!(stats[&flaky_url].circuit, CircuitState::Open);
+        assert_eq!(provider.get_fastest_provider(), slow_url);
+
+        // A successful trial probe closes the circuit.
+        sleep(Duration::from_millis(500)).await;
+        assert_eq!(
+            provider.provider_stats()[&flaky_url].circuit,
+            CircuitState::Closed
+        );
+        assert_eq!(provider.get_fastest_provider(), flaky_url);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_cooldown() {
+        let rate_limited = Arc::new(AtomicBool::new(false));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let limited_url = MockProvider::new()
+            .handler({
+                let rate_limited = rate_limited.clone();
+                let requests = requests.clone();
+                move |_, _| {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    if rate_limited.load(Ordering::SeqCst) {
+                        json!({"error": {
+                            "code": -32005,
+                            "message": "daily request count exceeded, request rate limited",
+                            "data": {"rate": {"backoff_seconds": 1}}
+                        }})
+                    } else {
+                        json!("mock/v1.0.0")
+                    }
+                }
+            })
+            .spawn()
+            .await;
+        let other_url = MockProvider::new()
+            .delay(Duration::from_millis(30))
+            .spawn()
+            .await;
+        let provider = ClosestWeb3RpcProviderSelector::init(
+            vec![limited_url.clone(), other_url.clone()],
+            Duration::from_millis(100),
+        );
+        provider.wait_until_ready().await;
+        assert_eq!(provider.get_fastest_provider(), limited_url);
+
+        // Rate limited requests are retried on the next provider.
+        rate_limited.store(true, Ordering::SeqCst);
+        let response = provider
+            .request("web3_clientVersion", json!([]))
+            .await
+            .unwrap();
+        assert_eq!(response.provider, other_url);
+        assert_eq!(
+            response.failed_attempts,
+            vec![(
+                limited_url.clone(),
+                ProbeError::RateLimited {
+                    retry_after: Some(Duration::from_secs(1))
+                }
+            )]
+        );
+
+        // The provider is neither selected nor probed while it cools down.
+        let cooldown = provider.provider_stats()[&limited_url].cooldown.unwrap();
+        assert!(cooldown <= Duration::from_secs(1));
+        assert_eq!(provider.get_fastest_provider(), other_url);
+        let sent = requests.load(Ordering::SeqCst);
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(requests.load(Ordering::SeqCst), sent);
+
+        // The provider is selected again once the cooldown is over.
+        rate_limited.store(false, Ordering::SeqCst);
+        sleep(Duration::from_millis(1000)).await;
+        assert_eq!(provider.provider_stats()[&limited_url].cooldown, None);
+        assert_eq!(provider.get_fastest_provider(), limited_url);
+    }
 }
@@ -0,0 +1,328 @@
+//! A minimal JSON-RPC server used by the tests to simulate Web3 providers without network access.
+
+// Standard library modules
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+// External libraries
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::sleep,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+
+/// Computes the `result` of a JSON-RPC call from its method name and params.
+type Handler = Arc<dyn Fn(&str, &Value) -> Value + Send + Sync>;
+
+/// Computes the delay applied before answering a request from the number of requests received before it.
+type Delay = Arc<dyn Fn(usize) -> Duration + Send + Sync>;
+
+/// A mock Web3 provider answering JSON-RPC requests over HTTP, WebSocket or IPC.
+pub struct MockProvider {
+    /// Delay applied before answering each request.
+    delay: Delay,
+
+    /// Number of requests received so far.
+    requests: AtomicUsize,
+
+    /// Handler producing the result of each call.
+    handler: Handler,
+
+    /// Number of requests answered before a WebSocket connection is closed by the provider.
+    close_after: Option<usize>,
+
+    /// Number of requests answered before a WebSocket connection stops answering while staying open.
+    stall_after: Option<usize>,
+
+    /// HTTP status code of every answer.
+    status: u16,
+
+    /// Raw body sent instead of the JSON-RPC response, if any.
+    body: Option<String>,
+
+    /// Counter of the HTTP connections accepted by the provider, if counted.
+    connections: Option<Arc<AtomicUsize>>,
+
+    /// Header every HTTP request must carry, as a lowercase `name: value` line. Requests without it are
+    /// answered with status 401.
+    required_header: Option<String>,
+
+    /// Additional header lines sent with every HTTP answer.
+    response_headers: Vec<String>,
+}
+
+impl MockProvider {
+    /// Creates a mock provider answering every call with `"mock/v1.0.0"`.
+    pub fn new() -> Self {
+        MockProvider {
+            delay: Arc::new(|_| Duration::ZERO),
+            requests: AtomicUsize::new(0),
+            handler: Arc::new(|_, _| Value::String("mock/v1.0.0".to_string())),
+            close_after: None,
+            stall_after: None,
+            status: 200,
+            body: None,
+            connections: None,
+            required_header: None,
+            response_headers: Vec::new(),
+        }
+    }
+
+    /// Delays every answer by `delay`.
+    pub fn delay(self, delay: Duration) -> Self {
+        self.delays(move |_| delay)
+    }
+
+    /// Delays every answer by the duration computed by `delay` from the number of requests received before.
+    pub fn delays(mut self, delay: impl Fn(usize) -> Duration + Send + Sync + 'static) -> Self {
+        self.delay = Arc::new(delay);
+        self
+    }
+
+    /// Answers calls with the result computed by `handler` from the method name and params.
+    pub fn handler(
+        mut self,
+        handler: impl Fn(&str, &Value) -> Value + Send + Sync + 'static,
+    ) -> Self {
+        self.handler = Arc::new(handler);
+        self
+    }
+
+    /// Answers HTTP requests with the `status` code.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Answers every request with the raw `body` instead of a JSON-RPC response.
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+
+    /// Increments `connections` whenever an HTTP connection is accepted.
+    pub fn count_connections(mut self, connections: Arc<AtomicUsize>) -> Self {
+        self.connections = Some(connections);
+        self
+    }
+
+    /// Answers HTTP requests without the header `name` set to `value` with status 401.
+    pub fn require_header(mut self, name: &str, value: &str) -> Self {
+        self.required_header = Some(format!("{}: {}", name, value).to_lowercase());
+        self
+    }
+
+    /// Sends the header `name` set to `value` with every HTTP answer.
+    pub fn response_header(mut self, name: &str, value: &str) -> Self {
+        self.response_headers
+            .push(format!("{}: {}\r\n", name, value));
+        self
+    }
+
+    /// Closes every WebSocket connection after answering `requests` requests on it.
+    pub fn close_after(mut self, requests: usize) -> Self {
+        self.close_after = Some(requests);
+        self
+    }
+
+    /// Stops answering on every WebSocket connection after answering `requests` requests on it, while keeping
+    /// the connection open.
+    pub fn stall_after(mut self, requests: usize) -> Self {
+        self.stall_after = Some(requests);
+        self
+    }
+
+    /// Binds the provider to a random local port and returns its WebSocket URL.
+    pub async fn spawn_ws(self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let provider = Arc::new(self);
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(provider.clone().serve_ws(socket));
+            }
+        });
+
+        url
+    }
+
+    /// Binds the provider to a fresh Unix domain socket and returns its path.
+    ///
+    /// Responses are written back to back without newlines, like some nodes do.
+    #[cfg(unix)]
+    pub async fn spawn_ipc(self) -> String {
+        static SOCKET_COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "web3-closest-provider-{}-{}.ipc",
+            std::process::id(),
+            SOCKET_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let provider = Arc::new(self);
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(provider.clone().serve_ipc(socket));
+            }
+        });
+
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Binds the provider to a random local port and returns its URL.
+    pub async fn spawn(self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let provider = Arc::new(self);
+
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                if let Some(connections) = &provider.connections {
+                    connections.fetch_add(1, Ordering::Relaxed);
+                }
+                tokio::spawn(provider.clone().serve(socket));
+            }
+        });
+
+        url
+    }
+
+    /// Serves HTTP/1.1 requests on a single connection until the client closes it.
+    async fn serve(self: Arc<Self>, mut socket: TcpStream) {
+        let mut buffer = Vec::new();
+
+        loop {
+            // Read until the end of the request headers.
+            let header_end = loop {
+                if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break position + 4;
+                }
+                let mut chunk = [0u8; 4096];
+                match socket.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+            };
+
+            // Read the request body according to its content length.
+            let headers = String::from_utf8_lossy(&buffer[..header_end]).to_lowercase();
+            let content_length = headers
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            while buffer.len() < header_end + content_length {
+                let mut chunk = [0u8; 4096];
+                match socket.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+            }
+            let body: Vec<u8> = buffer
+                .drain(..header_end + content_length)
+                .skip(header_end)
+                .collect();
+
+            // Answer the JSON-RPC call, unless a required header is missing.
+            let response = self.respond(&body).await;
+            let status = match &self.required_header {
+                Some(header) if !headers.lines().any(|line| line == header) => 401,
+                _ => self.status,
+            };
+
+            let message = format!(
+                "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\n{}\r\n{}",
+                status,
+                response.len(),
+                self.response_headers.concat(),
+                response
+            );
+            if socket.write_all(message.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Serves JSON-RPC requests over a WebSocket connection until the client closes it.
+    async fn serve_ws(self: Arc<Self>, socket: TcpStream) {
+        let Ok(mut stream) = accept_async(socket).await else {
+            return;
+        };
+        let mut answered = 0;
+
+        while let Some(Ok(message)) = stream.next().await {
+            let Message::Text(text) = message else {
+                continue;
+            };
+            if self.stall_after == Some(answered) {
+                continue;
+            }
+
+            let response = self.respond(text.as_bytes()).await;
+            if stream.send(Message::Text(response)).await.is_err() {
+                return;
+            }
+
+            answered += 1;
+            if self.close_after == Some(answered) {
+                let _ = stream.close(None).await;
+                return;
+            }
+        }
+    }
+
+    /// Serves newline delimited JSON-RPC requests over a Unix domain socket until the client closes it.
+    #[cfg(unix)]
+    async fn serve_ipc(self: Arc<Self>, socket: UnixStream) {
+        let (reader, mut writer) = socket.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let response = self.respond(line.as_bytes()).await;
+            if writer.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Computes the JSON-RPC response to a request body after the configured delay.
+    async fn respond(&self, body: &[u8]) -> String {
+        let request: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        let method = request["method"].as_str().unwrap_or_default();
+        let result = (self.handler)(method, &request["params"]);
+
+        sleep((self.delay)(self.requests.fetch_add(1, Ordering::Relaxed))).await;
+
+        if let Some(body) = &self.body {
+            return body.clone();
+        }
+
+        // Handlers may answer with a JSON-RPC error object under the `error` key.
+        match result.get("error") {
+            Some(error) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": error,
+            }),
+            None => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": result,
+            }),
+        }
+        .to_string()
+    }
+}
@@ -0,0 +1,161 @@
+// External libraries
+use serde_json::Value;
+
+/// Describes the JSON-RPC call used to measure the response time of a provider.
+///
+/// A sample only counts when the provider answers without an error and its `result` matches `expected`,
+/// so gateways answering from a cache with a wrong payload do not win the ranking.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{ExpectedResult, ProbeSpec};
+///
+/// let probe = ProbeSpec::new(
+///     "eth_getBlockByNumber",
+///     serde_json::json!(["latest", false]),
+///     ExpectedResult::Object,
+/// );
+/// assert_eq!(probe, ProbeSpec::latest_block());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeSpec {
+    /// The JSON-RPC method to call.
+    pub method: String,
+
+    /// The params passed to the method.
+    pub params: Value,
+
+    /// The shape the `result` of the call must have.
+    pub expected: ExpectedResult,
+}
+
+/// The shape the `result` field of a probe response must have for the sample to count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedResult {
+    /// Any result is accepted, including `null`.
+    Any,
+
+    /// Any result except `null`.
+    NonNull,
+
+    /// A JSON string.
+    String,
+
+    /// A hex encoded quantity such as `"0x10d4f"`.
+    HexQuantity,
+
+    /// A JSON object.
+    Object,
+}
+
+impl ProbeSpec {
+    /// Creates a probe calling `method` with `params` and validating the result against `expected`.
+    pub fn new(method: impl Into<String>, params: Value, expected: ExpectedResult) -> Self {
+        ProbeSpec {
+            method: method.into(),
+            params,
+            expected,
+        }
+    }
+
+    /// Probes with `web3_clientVersion`, the historical default.
+    pub fn client_version() -> Self {
+        Self::new(
+            "web3_clientVersion",
+            Value::Array(vec![]),
+            ExpectedResult::String,
+        )
+    }
+
+    /// Probes with `eth_blockNumber`, which has to be answered by a node.
+    pub fn block_number() -> Self {
+        Self::new(
+            "eth_blockNumber",
+            Value::Array(vec![]),
+            ExpectedResult::HexQuantity,
+        )
+    }
+
+    /// Probes with `eth_chainId`.
+    pub fn chain_id() -> Self {
+        Self::new(
+            "eth_chainId",
+            Value::Array(vec![]),
+            ExpectedResult::HexQuantity,
+        )
+    }
+
+    /// Probes with `eth_getBlockByNumber("latest", false)`.
+    pub fn latest_block() -> Self {
+        Self::new(
+            "eth_getBlockByNumber",
+            serde_json::json!(["latest", false]),
+            ExpectedResult::Object,
+        )
+    }
+
+    /// Builds the JSON-RPC request body of the probe.
+    pub(crate) fn request_body(&self) -> Value {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": self.method,
+            "params": self.params,
+            "id": 1
+        })
+    }
+}
+
+impl Default for ProbeSpec {
+    fn default() -> Self {
+        Self::client_version()
+    }
+}
+
+impl ExpectedResult {
+    /// Checks whether `result` has the expected shape.
+    pub fn matches(&self, result: &Value) -> bool {
+        match self {
+            ExpectedResult::Any => true,
+            ExpectedResult::NonNull => !result.is_null(),
+            ExpectedResult::String => result.is_string(),
+            ExpectedResult::HexQuantity => result.as_str().and_then(parse_hex_quantity).is_some(),
+            ExpectedResult::Object => result.is_object(),
+        }
+    }
+}
+
+/// Parses a hex encoded JSON-RPC quantity such as `"0x10d4f"`.
+pub(crate) fn parse_hex_quantity(value: &str) -> Option<u64> {
+    let digits = value.strip_prefix("0x")?;
+    if digits.is_empty() {
+        return None;
+    }
+    u64::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::probe::{parse_hex_quantity, ExpectedResult};
+    use serde_json::json;
+
+    #[test]
+    fn test_expected_result_matches() {
+        assert!(ExpectedResult::Any.matches(&json!(null)));
+        assert!(!ExpectedResult::NonNull.matches(&json!(null)));
+        assert!(ExpectedResult::String.matches(&json!("Geth/v1.13.0")));
+        assert!(ExpectedResult::HexQuantity.matches(&json!("0x10d4f")));
+        assert!(!ExpectedResult::HexQuantity.matches(&json!("Geth/v1.13.0")));
+        assert!(!ExpectedResult::HexQuantity.matches(&json!(42)));
+        assert!(ExpectedResult::Object.matches(&json!({ "number": "0x1" })));
+        assert!(!ExpectedResult::Object.matches(&json!("0x1")));
+    }
+
+    #[test]
+    fn test_parse_hex_quantity() {
+        assert_eq!(parse_hex_quantity("0x10d4f"), Some(68943));
+        assert_eq!(parse_hex_quantity("0x0"), Some(0));
+        assert_eq!(parse_hex_quantity("0x"), None);
+        assert_eq!(parse_hex_quantity("10d4f"), None);
+    }
+}
@@ -0,0 +1,425 @@
+// Standard library modules
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// External libraries
+use serde_json::Value;
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinSet,
+    time::{sleep, timeout, timeout_at},
+};
+
+// Internal modules
+use crate::{
+    config::{LatencyMode, SelectorConfig},
+    error::{ChainIdMismatch, ProbeError},
+    events::SelectorEvent,
+    probe::{parse_hex_quantity, ProbeSpec},
+    stats::{LatencyPhases, ProviderState},
+    sticky::StickySelection,
+    strategy::Ranking,
+    transport::{Transport, TransportPool},
+};
+
+/// The timings of a successful probe request.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// The response time of the request.
+    response_time: Duration,
+
+    /// The time spent in each phase of the request, for HTTP providers.
+    phases: Option<LatencyPhases>,
+}
+
+/// The measurements of a single probe.
+#[derive(Debug, Clone)]
+struct ProbeResult {
+    /// The timings of the probe, or the error it failed with.
+    sample: Result<Sample, ProbeError>,
+
+    /// The timings of the probe over a new connection, if measured separately.
+    cold_sample: Option<Result<Sample, ProbeError>>,
+
+    /// The block number reported by the provider, if requested and known.
+    block_number: Option<u64>,
+
+    /// The chain ID reported by the provider, if requested and known.
+    chain_id: Option<u64>,
+}
+
+impl ProbeResult {
+    /// Creates the result of a probe which failed with `error`.
+    fn failed(error: ProbeError) -> Self {
+        ProbeResult {
+            sample: Err(error),
+            cold_sample: None,
+            block_number: None,
+            chain_id: None,
+        }
+    }
+}
+
+/// The background task periodically probing the providers of a balancer.
+pub(crate) struct Prober {
+    /// The URLs of the providers, shared with the balancer which may change them at any time.
+    providers: Arc<Mutex<Vec<String>>>,
+
+    /// The URLs of the providers probed in the current round.
+    urls: Vec<String>,
+
+    /// The transport used to reach each provider.
+    transports: Arc<TransportPool>,
+
+    /// Shared map storing the measurements of each provider.
+    provider_states: Arc<Mutex<HashMap<String, ProviderState>>>,
+
+    /// The provider kept selected when stickiness is enabled.
+    selection: Arc<Mutex<StickySelection>>,
+
+    /// The configuration of the balancer.
+    config: Arc<SelectorConfig>,
+
+    /// Sender broadcasting the events of the balancer to its subscribers.
+    events: broadcast::Sender<SelectorEvent>,
+
+    /// Sender notifying the balancer of the number of completed rounds.
+    rounds: watch::Sender<usize>,
+
+    /// The chain IDs reported by the providers.
+    chain_ids: HashMap<String, u64>,
+
+    /// The time the chain IDs of all providers were last verified.
+    last_chain_id_check: Option<Instant>,
+}
+
+impl Prober {
+    /// Creates the prober of the given providers. Their transports are created on the first round.
+    pub(crate) fn new(
+        providers: Arc<Mutex<Vec<String>>>,
+        transports: Arc<TransportPool>,
+        provider_states: Arc<Mutex<HashMap<String, ProviderState>>>,
+        selection: Arc<Mutex<StickySelection>>,
+        config: Arc<SelectorConfig>,
+        events: broadcast::Sender<SelectorEvent>,
+        rounds: watch::Sender<usize>,
+    ) -> Self {
+        Prober {
+            providers,
+            urls: Vec::new(),
+            transports,
+            provider_states,
+            selection,
+            config,
+            events,
+            rounds,
+            chain_ids: HashMap::new(),
+            last_chain_id_check: None,
+        }
+    }
+
+    /// Asynchronously checks the response times of the providers and updates the provider state map
+    /// until a message is received on `receiver`.
+    ///
+    /// Every round probes all providers concurrently and waits at most until the round deadline for them to
+    /// answer, so each provider receives a fresh sample per interval regardless of how many providers are
+    /// configured.
+    pub(crate) async fn run(mut self, receiver: watch::Receiver<()>) {
+        loop {
+            // Clone the receiver to avoid borrowing issues within the select macro.
+            let mut receiver_clone = receiver.clone();
+
+            // Record the start of the round so the next one starts exactly one interval later.
+            let round_start = Instant::now();
+
+            // Select between different branches based on received messages or timeouts.
+            tokio::select! {
+                // Handle a message from the receiver indicating destruction.
+                _ = receiver_clone.changed() => {
+                    break;
+                }
+
+                // Probe all URLs concurrently and wait for the next interval.
+                _ = async {
+                    self.perform_round(round_start).await;
+
+                    // Wait for the remainder of the interval duration to pass.
+                    sleep(self.config.checking_interval.saturating_sub(round_start.elapsed())).await;
+                } => {}
+            }
+        }
+    }
+
+    /// Probes all providers once and records the measurements of the round.
+    async fn perform_round(&mut self, round_start: Instant) {
+        self.update_providers();
+        let chain_id_check_urls = self.chain_id_check_urls(round_start);
+        let round_results = self
+            .perform_response_time_round(round_start, &chain_id_check_urls)
+            .await;
+
+        // Remember the verified chain IDs and report newly detected mismatches.
+        for (url, result) in round_results.iter() {
+            if let Some(chain_id) = result.chain_id {
+                let previous = self.chain_ids.insert(url.clone(), chain_id);
+                match self.config.expected_chain_id {
+                    Some(expected) if chain_id != expected && previous != Some(chain_id) => {
+                        let _ = self
+                            .events
+                            .send(SelectorEvent::ChainIdMismatch(ChainIdMismatch {
+                                url: url.clone(),
+                                expected,
+                                actual: chain_id,
+                            }));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Acquire a lock on the provider state map once and record all samples of the round, except for the
+        // providers removed in the meantime.
+        let providers = self.providers.lock().unwrap();
+        let mut provider_states = self.provider_states.lock().unwrap();
+        for (url, result) in round_results {
+            if !providers.contains(&url) {
+                continue;
+            }
+            let state = provider_states
+                .entry(url.clone())
+                .or_insert_with(|| ProviderState::new(&self.config));
+            if let Ok(Sample {
+                phases: Some(phases),
+                ..
+            }) = result.sample
+            {
+                state.phases = Some(phases);
+            }
+            state.record(result.sample.map(|sample| sample.response_time));
+            if let Some(cold_sample) = result.cold_sample {
+                if let Ok(Sample {
+                    phases: Some(phases),
+                    ..
+                }) = cold_sample
+                {
+                    state.cold_phases = Some(phases);
+                }
+                state
+                    .cold_window
+                    .record(cold_sample.ok().map(|sample| sample.response_time));
+            }
+            state.block_number = result.block_number;
+            state.chain_id = self.chain_ids.get(&url).copied();
+        }
+
+        // Reconsider the sticky selection with the measurements of the round.
+        if let Some(stickiness) = &self.config.stickiness {
+            let ranking = Ranking::new(&provider_states, &self.config);
+            self.selection.lock().unwrap().update(
+                &ranking,
+                stickiness,
+                self.config.strategy.as_ref(),
+            );
+        }
+        drop(provider_states);
+        drop(providers);
+
+        // Notify the balancer waiting until it is ready.
+        self.rounds.send_modify(|rounds| *rounds += 1);
+    }
+
+    /// Takes the latest changes to the providers into account, creating the transports of the added providers
+    /// and closing those of the removed ones.
+    fn update_providers(&mut self) {
+        self.urls = self.providers.lock().unwrap().clone();
+
+        self.transports.update(&self.urls, &self.config);
+        self.chain_ids.retain(|url, _| self.urls.contains(url));
+    }
+
+    /// Selects the providers whose chain ID has to be verified during the round starting at `round_start`.
+    ///
+    /// When an expected chain ID is configured, the chain ID of every provider is verified on the first round,
+    /// then every `chain_id_check_interval`, and on every round for providers whose chain ID is still unknown.
+    fn chain_id_check_urls(&mut self, round_start: Instant) -> HashSet<String> {
+        if self.config.expected_chain_id.is_none() {
+            return HashSet::new();
+        }
+
+        let check_all = self.last_chain_id_check.is_none_or(|checked_at| {
+            round_start.duration_since(checked_at) >= self.config.chain_id_check_interval
+        });
+        if check_all {
+            self.last_chain_id_check = Some(round_start);
+        }
+
+        self.urls
+            .iter()
+            .filter(|url| check_all || !self.chain_ids.contains_key(*url))
+            .cloned()
+            .collect()
+    }
+
+    /// Probes all URLs in parallel and returns the measurements of each of them.
+    ///
+    /// Probes that do not finish before the round deadline are recorded as timed out.
+    async fn perform_response_time_round(
+        &self,
+        round_start: Instant,
+        chain_id_check_urls: &HashSet<String>,
+    ) -> HashMap<String, ProbeResult> {
+        let mut probes = JoinSet::new();
+        let deadline = round_start
+            + self
+                .config
+                .round_deadline
+                .unwrap_or(self.config.checking_interval);
+
+        // Find the providers cooling down after rate limiting the balancer, which are not probed until they may be
+        // used again.
+        let cooling_down: HashSet<String> = self
+            .provider_states
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, state)| state.cooldown().is_some())
+            .map(|(url, _)| url.clone())
+            .collect();
+
+        // Spawn one probe per URL, each bounded by the round deadline.
+        for url in &self.urls {
+            if cooling_down.contains(url) {
+                continue;
+            }
+            let Some(transport) = self.transports.get(url) else {
+                continue;
+            };
+            let url = url.clone();
+            let client = self.transports.client().clone();
+            let config = self.config.clone();
+            let check_chain_id = chain_id_check_urls.contains(&url);
+            probes.spawn(async move {
+                let result = timeout_at(
+                    deadline.into(),
+                    Self::perform_probe(&client, &transport, &config, check_chain_id),
+                )
+                .await
+                .unwrap_or_else(|_| ProbeResult::failed(ProbeError::Timeout));
+                (url, result)
+            });
+        }
+
+        // Collect the results of all probes.
+        let mut round_results = HashMap::with_capacity(self.urls.len());
+        while let Some(result) = probes.join_next().await {
+            if let Ok((url, result)) = result {
+                round_results.insert(url, result);
+            }
+        }
+
+        round_results
+    }
+
+    /// Probes a single provider and returns its measurements.
+    ///
+    /// When block lag tracking is enabled, the block number is taken from the probe itself if it calls
+    /// `eth_blockNumber`, and requested separately otherwise. The chain ID is only requested when
+    /// `check_chain_id` is set. Depending on the latency mode, HTTP providers are probed over a new connection
+    /// instead of a warm one, or over both.
+    async fn perform_probe(
+        client: &reqwest::Client,
+        transport: &Transport,
+        config: &SelectorConfig,
+        check_chain_id: bool,
+    ) -> ProbeResult {
+        let cold = config.latency_mode == LatencyMode::Cold;
+        let (sample, result) =
+            match Self::perform_probe_request(client, transport, &config.probe, config, cold).await
+            {
+                Ok(response) => response,
+                Err(e) => return ProbeResult::failed(e),
+            };
+
+        let block_number = if config.probe.method == "eth_blockNumber" {
+            result.as_str().and_then(parse_hex_quantity)
+        } else if config.max_block_lag.is_some() {
+            Self::perform_probe_request(
+                client,
+                transport,
+                &ProbeSpec::block_number(),
+                config,
+                false,
+            )
+            .await
+            .ok()
+            .and_then(|(_, result)| result.as_str().and_then(parse_hex_quantity))
+        } else {
+            None
+        };
+
+        let chain_id = if check_chain_id {
+            Self::perform_probe_request(client, transport, &ProbeSpec::chain_id(), config, false)
+                .await
+                .ok()
+                .and_then(|(_, result)| result.as_str().and_then(parse_hex_quantity))
+        } else {
+            None
+        };
+
+        let cold_sample = if config.latency_mode == LatencyMode::ColdAndWarm && transport.is_http()
+        {
+            Some(
+                Self::perform_probe_request(client, transport, &config.probe, config, true)
+                    .await
+                    .map(|(sample, _)| sample),
+            )
+        } else {
+            None
+        };
+
+        ProbeResult {
+            sample: Ok(sample),
+            cold_sample,
+            block_number,
+            chain_id,
+        }
+    }
+
+    /// Sends the probe JSON-RPC request to a provider and returns the timings and result or an error.
+    ///
+    /// HTTP requests are sent over a new connection when `cold` is set. The request fails with a timeout if it
+    /// does not complete within the probe timeout.
+    async fn perform_probe_request(
+        client: &reqwest::Client,
+        transport: &Transport,
+        probe: &ProbeSpec,
+        config: &SelectorConfig,
+        cold: bool,
+    ) -> Result<(Sample, Value), ProbeError> {
+        // Send the JSON-RPC request and handle potential errors.
+        let timed_response = timeout(
+            config.probe_timeout,
+            transport.request(client, &probe.request_body(), cold),
+        )
+        .await
+        .map_err(|_| ProbeError::Timeout)??;
+
+        // Check if the response contains an error field.
+        let result = timed_response.response.into_result()?;
+
+        // Check that the result has the shape expected by the probe.
+        if !probe.expected.matches(&result) {
+            return Err(ProbeError::UnexpectedResult(result.to_string()));
+        }
+
+        // Return the timings along with the result.
+        let sample = Sample {
+            response_time: timed_response.response_time,
+            phases: timed_response.phases,
+        };
+        Ok((sample, result))
+    }
+}
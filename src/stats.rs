@@ -0,0 +1,661 @@
+// Standard library modules
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Internal modules
+use crate::{
+    breaker::{CircuitBreaker, CircuitState},
+    config::{HealthPolicy, RateLimitPolicy, SelectorConfig},
+    error::ProbeError,
+};
+
+/// Rolling statistics of a provider, computed over its latest probe samples.
+///
+/// Latency statistics only consider successful probes and are `None` until a probe succeeds. When real requests
+/// are reported to the balancer, their latest and average latencies are blended with the ones of the probes
+/// according to `SelectorConfig::passive_weight`, while their percentiles are reported separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderStats {
+    /// The response time of the latest probe, blended with the latest reported request. `None` if the probe
+    /// failed and no request was reported.
+    pub last: Option<Duration>,
+
+    /// The exponentially weighted moving average of the response times, blended with the one of reported
+    /// requests.
+    pub ewma: Option<Duration>,
+
+    /// The median response time of the probes within the sample window.
+    pub p50: Option<Duration>,
+
+    /// The 90th percentile of the response times of the probes within the sample window.
+    pub p90: Option<Duration>,
+
+    /// The 95th percentile of the response times of the probes within the sample window.
+    pub p95: Option<Duration>,
+
+    /// The 99th percentile of the response times of the probes within the sample window.
+    pub p99: Option<Duration>,
+
+    /// The mean absolute difference between consecutive response times within the sample window.
+    pub jitter: Option<Duration>,
+
+    /// The share of successful probes within the sample window, between `0.0` and `1.0`.
+    pub success_rate: f64,
+
+    /// The number of probes within the sample window, successful or not.
+    pub samples: usize,
+
+    /// The moving average of the response times of real requests, `None` if none succeeded yet.
+    pub passive_latency: Option<Duration>,
+
+    /// The number of real requests within the sample window, successful or not.
+    pub passive_samples: usize,
+
+    /// The median response time of the real requests within the sample window.
+    pub passive_p50: Option<Duration>,
+
+    /// The 90th percentile of the response times of the real requests within the sample window.
+    pub passive_p90: Option<Duration>,
+
+    /// The 95th percentile of the response times of the real requests within the sample window.
+    pub passive_p95: Option<Duration>,
+
+    /// The 99th percentile of the response times of the real requests within the sample window.
+    pub passive_p99: Option<Duration>,
+
+    /// The latest block number reported by the provider, if known.
+    pub block_number: Option<u64>,
+
+    /// The chain ID reported by the provider, if known.
+    pub chain_id: Option<u64>,
+
+    /// The moving average of the response times measured over new connections, when the latency mode
+    /// measures them separately.
+    pub cold_latency: Option<Duration>,
+
+    /// The phases of the latest successful probe, for HTTP providers.
+    pub phases: Option<LatencyPhases>,
+
+    /// The phases of the latest successful probe over a new connection, when the latency mode measures them
+    /// separately.
+    pub cold_phases: Option<LatencyPhases>,
+
+    /// The health of the provider according to its latest probes.
+    pub health: ProviderHealth,
+
+    /// The error of the latest failed probe, if any probe failed.
+    pub last_error: Option<ProbeError>,
+
+    /// The state of the circuit breaker of the provider, always closed without a circuit breaker policy.
+    pub circuit: CircuitState,
+
+    /// The remaining time the provider is neither selected nor probed because it rate limited the balancer,
+    /// `None` if it is not cooling down.
+    pub cooldown: Option<Duration>,
+}
+
+/// The time spent in each phase of a probe of an HTTP provider.
+///
+/// Comparing the connection phases with the time to first byte tells whether a provider is slow because of
+/// network distance or because its node is overloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyPhases {
+    /// The time spent resolving the host name, `None` if the probe reused a connection or the URL contains an
+    /// IP address.
+    pub dns: Option<Duration>,
+
+    /// The time spent establishing the TCP connection, `None` if the probe reused a connection.
+    pub connect: Option<Duration>,
+
+    /// The time spent in the TLS handshake, `None` if the probe reused a connection or the provider is not
+    /// reached over HTTPS.
+    pub tls: Option<Duration>,
+
+    /// The time from sending the request to receiving the response headers. Over pooled connections, this
+    /// includes establishing a connection when none was idle.
+    pub ttfb: Duration,
+
+    /// The time spent reading the response body.
+    pub body: Duration,
+}
+
+/// The health of a provider according to its latest probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderHealth {
+    /// The latest probe succeeded.
+    Healthy,
+
+    /// The latest probes failed, but not enough of them to consider the provider down. Degraded providers
+    /// are only selected when no healthy provider is available.
+    Degraded {
+        /// The error of the latest failed probe.
+        last_error: ProbeError,
+    },
+
+    /// Too many consecutive probes failed. Down providers are never selected until a probe succeeds again.
+    Down {
+        /// The error of the latest failed probe.
+        last_error: ProbeError,
+    },
+}
+
+/// The statistic used to rank providers by latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionStatistic {
+    /// The response time of the latest probe.
+    Last,
+
+    /// The exponentially weighted moving average of the response times.
+    #[default]
+    Ewma,
+
+    /// The median response time.
+    P50,
+
+    /// The 90th percentile of the response times.
+    P90,
+
+    /// The 95th percentile of the response times.
+    P95,
+
+    /// The 99th percentile of the response times.
+    P99,
+}
+
+impl ProviderStats {
+    /// Returns the value of `statistic`, `None` if no probe succeeded within the sample window.
+    pub fn latency(&self, statistic: SelectionStatistic) -> Option<Duration> {
+        match statistic {
+            SelectionStatistic::Last => self.last,
+            SelectionStatistic::Ewma => self.ewma,
+            SelectionStatistic::P50 => self.p50,
+            SelectionStatistic::P90 => self.p90,
+            SelectionStatistic::P95 => self.p95,
+            SelectionStatistic::P99 => self.p99,
+        }
+    }
+}
+
+/// The measurements of a single provider.
+#[derive(Debug, Clone)]
+pub(crate) struct ProviderState {
+    /// The latest probe samples of the provider.
+    pub(crate) window: SampleWindow,
+
+    /// The latest outcomes of real requests sent to the provider.
+    pub(crate) passive_window: SampleWindow,
+
+    /// The weight of the real requests in the latency statistics, between `0.0` and `1.0`.
+    passive_weight: f64,
+
+    /// The latest samples measured over new connections, when measured separately.
+    pub(crate) cold_window: SampleWindow,
+
+    /// The phases of the latest successful probe, for HTTP providers.
+    pub(crate) phases: Option<LatencyPhases>,
+
+    /// The phases of the latest successful probe over a new connection, when measured separately.
+    pub(crate) cold_phases: Option<LatencyPhases>,
+
+    /// The latest block number reported by the provider, if known.
+    pub(crate) block_number: Option<u64>,
+
+    /// The chain ID reported by the provider, if known.
+    pub(crate) chain_id: Option<u64>,
+
+    /// The number of consecutive failed probes, timed out probes counting as their penalty.
+    consecutive_failures: usize,
+
+    /// The error of the latest failed probe, if any probe failed.
+    last_error: Option<ProbeError>,
+
+    /// Decides when the provider is considered down.
+    health_policy: HealthPolicy,
+
+    /// Stops the selection of the provider while it fails too often, if a circuit breaker policy is set.
+    breaker: Option<CircuitBreaker>,
+
+    /// When the provider may be used again after rate limiting the balancer, if it did.
+    cooldown_until: Option<Instant>,
+
+    /// Decides how long the provider cools down after rate limiting the balancer.
+    rate_limit_policy: RateLimitPolicy,
+}
+
+impl ProviderState {
+    /// Creates the state of a provider which was not probed yet.
+    pub(crate) fn new(config: &SelectorConfig) -> Self {
+        ProviderState {
+            window: SampleWindow::new(config.sample_window, config.ewma_alpha),
+            cold_window: SampleWindow::new(config.sample_window, config.ewma_alpha),
+            passive_window: SampleWindow::new(config.sample_window, config.ewma_alpha),
+            passive_weight: config.passive_weight,
+            phases: None,
+            cold_phases: None,
+            block_number: None,
+            chain_id: None,
+            consecutive_failures: 0,
+            last_error: None,
+            health_policy: config.health,
+            breaker: config.circuit_breaker.map(CircuitBreaker::new),
+            cooldown_until: None,
+            rate_limit_policy: config.rate_limit,
+        }
+    }
+
+    /// Records the response time of a probe, or the error it failed with.
+    pub(crate) fn record(&mut self, result: Result<Duration, ProbeError>) {
+        self.window.record(result.as_ref().ok().copied());
+        self.record_outcome(result, true);
+    }
+
+    /// Records the response time of a real request, or the error it failed with.
+    ///
+    /// Failed requests count towards the health of the provider just like failed probes.
+    pub(crate) fn record_passive(&mut self, result: Result<Duration, ProbeError>) {
+        self.passive_window.record(result.as_ref().ok().copied());
+        self.record_outcome(result, false);
+    }
+
+    /// Updates the consecutive failures, the circuit breaker and the latest error with the outcome of a probe or
+    /// request, `probe` being whether it was a probe.
+    ///
+    /// A provider rate limiting the balancer only cools down: it is not failing, so neither its health nor its
+    /// circuit are affected.
+    fn record_outcome(&mut self, result: Result<Duration, ProbeError>, probe: bool) {
+        if let Err(ProbeError::RateLimited { retry_after }) = result {
+            let cooldown = retry_after
+                .unwrap_or(self.rate_limit_policy.default_cooldown)
+                .min(self.rate_limit_policy.max_cooldown);
+            self.cooldown_until = Some(Instant::now() + cooldown);
+            self.last_error = Some(ProbeError::RateLimited { retry_after });
+            return;
+        }
+
+        if let Some(breaker) = &mut self.breaker {
+            breaker.record(result.is_err(), probe);
+        }
+        match result {
+            Ok(_) => self.consecutive_failures = 0,
+            Err(error) => {
+                self.consecutive_failures += match error {
+                    ProbeError::Timeout => self.health_policy.timeout_penalty,
+                    _ => 1,
+                };
+                self.last_error = Some(error);
+            }
+        }
+    }
+
+    /// Returns the health of the provider according to its latest probes.
+    pub(crate) fn health(&self) -> ProviderHealth {
+        match (&self.last_error, self.consecutive_failures) {
+            (None, _) | (_, 0) => ProviderHealth::Healthy,
+            (Some(last_error), failures) if failures < self.health_policy.failures_before_down => {
+                ProviderHealth::Degraded {
+                    last_error: last_error.clone(),
+                }
+            }
+            (Some(last_error), _) => ProviderHealth::Down {
+                last_error: last_error.clone(),
+            },
+        }
+    }
+
+    /// Checks whether a probe or request to the provider succeeded within the sample windows. Providers that never
+    /// answered must not be selected, even before they are considered down.
+    pub(crate) fn has_answered(&self) -> bool {
+        self.window.has_success() || self.passive_window.has_success()
+    }
+
+    /// Checks whether the provider is considered down and must not be selected.
+    pub(crate) fn is_down(&self) -> bool {
+        self.consecutive_failures > 0
+            && self.consecutive_failures >= self.health_policy.failures_before_down
+    }
+
+    /// Returns the state of the circuit breaker of the provider.
+    pub(crate) fn circuit(&self) -> CircuitState {
+        self.breaker
+            .as_ref()
+            .map_or(CircuitState::Closed, CircuitBreaker::state)
+    }
+
+    /// Returns the remaining cooldown of the provider after it rate limited the balancer, `None` if it is not
+    /// cooling down.
+    pub(crate) fn cooldown(&self) -> Option<Duration> {
+        self.cooldown_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Checks whether the provider may be selected, i.e. it answered successfully, it is neither quarantined nor
+    /// down, its circuit is closed and it is not cooling down.
+    pub(crate) fn is_available(&self, config: &SelectorConfig) -> bool {
+        self.has_answered()
+            && !self.is_quarantined(config)
+            && !self.is_down()
+            && self.circuit() == CircuitState::Closed
+            && self.cooldown().is_none()
+    }
+
+    /// Computes the statistics of the provider.
+    pub(crate) fn stats(&self) -> ProviderStats {
+        let active = self.window.stats(self.block_number, self.chain_id);
+        let passive = self.passive_window.stats(None, None);
+        let blend = |active, passive| blend(active, passive, self.passive_weight);
+
+        ProviderStats {
+            last: blend(active.last, passive.last),
+            ewma: blend(active.ewma, passive.ewma),
+            passive_latency: passive.ewma,
+            passive_samples: passive.samples,
+            passive_p50: passive.p50,
+            passive_p90: passive.p90,
+            passive_p95: passive.p95,
+            passive_p99: passive.p99,
+            cold_latency: self.cold_window.ewma(),
+            phases: self.phases,
+            cold_phases: self.cold_phases,
+            health: self.health(),
+            last_error: self.last_error.clone(),
+            circuit: self.circuit(),
+            cooldown: self.cooldown(),
+            ..active
+        }
+    }
+
+    /// Checks whether the provider is excluded from the selection because its chain ID is not verified.
+    ///
+    /// Providers whose chain ID could not be determined yet are quarantined as well.
+    pub(crate) fn is_quarantined(&self, config: &SelectorConfig) -> bool {
+        match config.expected_chain_id {
+            Some(expected) => self.chain_id != Some(expected),
+            None => false,
+        }
+    }
+
+    /// Checks whether the provider lags too far behind the chain `head` to be preferred.
+    ///
+    /// Providers with an unknown block number are considered lagging when block lag tracking is enabled.
+    pub(crate) fn is_lagging(&self, config: &SelectorConfig, head: Option<u64>) -> bool {
+        match (config.max_block_lag, self.block_number, head) {
+            (None, _, _) => false,
+            (Some(max_block_lag), Some(block_number), Some(head)) => {
+                head.saturating_sub(block_number) > max_block_lag
+            }
+            (Some(_), _, _) => true,
+        }
+    }
+}
+
+/// A bounded window of the latest probe samples of a provider.
+#[derive(Debug, Clone)]
+pub(crate) struct SampleWindow {
+    /// The latest samples, oldest first. `None` stands for a failed probe.
+    samples: VecDeque<Option<Duration>>,
+
+    /// The maximum number of samples kept.
+    capacity: usize,
+
+    /// The smoothing factor of the moving average, between `0.0` and `1.0`.
+    alpha: f64,
+
+    /// The moving average of the successful response times in microseconds.
+    ewma: Option<f64>,
+}
+
+impl SampleWindow {
+    /// Creates an empty window keeping at most `capacity` samples.
+    pub(crate) fn new(capacity: usize, alpha: f64) -> Self {
+        SampleWindow {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            alpha,
+            ewma: None,
+        }
+    }
+
+    /// Records the response time of a probe, `None` if it failed.
+    pub(crate) fn record(&mut self, sample: Option<Duration>) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+
+        if let Some(sample) = sample {
+            let micros = sample.as_secs_f64() * 1_000_000.0;
+            self.ewma = Some(match self.ewma {
+                Some(ewma) => self.alpha * micros + (1.0 - self.alpha) * ewma,
+                None => micros,
+            });
+        }
+    }
+
+    /// Checks whether a probe within the window succeeded.
+    pub(crate) fn has_success(&self) -> bool {
+        self.samples.iter().any(Option::is_some)
+    }
+
+    /// Returns the response time of the latest probe, `None` if it failed or nothing was recorded yet.
+    pub(crate) fn last(&self) -> Option<Duration> {
+        self.samples.back().copied().flatten()
+    }
+
+    /// Returns the moving average of the successful response times, `None` if no probe succeeded yet.
+    pub(crate) fn ewma(&self) -> Option<Duration> {
+        self.ewma
+            .map(|ewma| Duration::from_nanos((ewma * 1_000.0) as u64))
+    }
+
+    /// Computes the statistics of the window.
+    pub(crate) fn stats(&self, block_number: Option<u64>, chain_id: Option<u64>) -> ProviderStats {
+        let successes: Vec<Duration> = self.samples.iter().flatten().copied().collect();
+        let mut sorted = successes.clone();
+        sorted.sort();
+
+        let jitter = match successes.len() {
+            0 | 1 => None,
+            n => {
+                let total: Duration = successes
+                    .windows(2)
+                    .map(|pair| pair[0].abs_diff(pair[1]))
+                    .sum();
+                Some(total / (n as u32 - 1))
+            }
+        };
+
+        let success_rate = match self.samples.len() {
+            0 => 0.0,
+            n => successes.len() as f64 / n as f64,
+        };
+
+        ProviderStats {
+            last: self.last(),
+            ewma: self.ewma(),
+            p50: percentile(&sorted, 50),
+            p90: percentile(&sorted, 90),
+            p95: percentile(&sorted, 95),
+            p99: percentile(&sorted, 99),
+            jitter,
+            success_rate,
+            samples: self.samples.len(),
+            passive_latency: None,
+            passive_samples: 0,
+            passive_p50: None,
+            passive_p90: None,
+            passive_p95: None,
+            passive_p99: None,
+            block_number,
+            chain_id,
+            cold_latency: None,
+            phases: None,
+            cold_phases: None,
+            health: ProviderHealth::Healthy,
+            last_error: None,
+            circuit: CircuitState::Closed,
+            cooldown: None,
+        }
+    }
+}
+
+/// Blends an `active` latency measured by probes with a `passive` one measured on real requests, giving
+/// `weight` to the passive one. A latency missing on either side leaves the other one, unless passive
+/// latencies are ignored.
+fn blend(active: Option<Duration>, passive: Option<Duration>, weight: f64) -> Option<Duration> {
+    match (active, passive) {
+        (Some(active), Some(passive)) => {
+            Some(active.mul_f64(1.0 - weight) + passive.mul_f64(weight))
+        }
+        (None, Some(passive)) if weight > 0.0 => Some(passive),
+        (active, _) => active,
+    }
+}
+
+/// Returns the nearest-rank `percentile` of `sorted` samples.
+fn percentile(sorted: &[Duration], percentile: usize) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percentile * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        breaker::{CircuitBreakerPolicy, CircuitState},
+        config::SelectorConfig,
+        error::ProbeError,
+        stats::{ProviderHealth, ProviderState, SampleWindow, SelectionStatistic},
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_sample_window_statistics() {
+        let mut window = SampleWindow::new(10, 0.5);
+        for millis in [10, 20, 30, 40] {
+            window.record(Some(Duration::from_millis(millis)));
+        }
+        window.record(None);
+
+        let stats = window.stats(Some(100), Some(1));
+        assert_eq!(stats.last, None);
+        assert_eq!(stats.ewma, Some(Duration::from_micros(31_250)));
+        assert_eq!(stats.p50, Some(Duration::from_millis(20)));
+        assert_eq!(stats.p90, Some(Duration::from_millis(40)));
+        assert_eq!(stats.p95, Some(Duration::from_millis(40)));
+        assert_eq!(stats.jitter, Some(Duration::from_millis(10)));
+        assert_eq!(stats.success_rate, 0.8);
+        assert_eq!(stats.samples, 5);
+        assert_eq!(
+            stats.latency(SelectionStatistic::P99),
+            Some(Duration::from_millis(40))
+        );
+    }
+
+    #[test]
+    fn test_sample_window_is_bounded() {
+        let mut window = SampleWindow::new(2, 0.5);
+        window.record(None);
+        window.record(Some(Duration::from_millis(10)));
+        window.record(Some(Duration::from_millis(20)));
+
+        let stats = window.stats(None, None);
+        assert_eq!(stats.samples, 2);
+        assert_eq!(stats.success_rate, 1.0);
+        assert_eq!(stats.last, Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_provider_health() {
+        let mut state = ProviderState::new(&SelectorConfig::default());
+        state.record(Ok(Duration::from_millis(10)));
+        assert_eq!(state.health(), ProviderHealth::Healthy);
+
+        state.record(Err(ProbeError::HttpStatus(502)));
+        assert_eq!(
+            state.health(),
+            ProviderHealth::Degraded {
+                last_error: ProbeError::HttpStatus(502)
+            }
+        );
+        assert!(!state.is_down());
+
+        state.record(Err(ProbeError::HttpStatus(502)));
+        assert!(state.is_down());
+
+        state.record(Ok(Duration::from_millis(10)));
+        assert_eq!(state.health(), ProviderHealth::Healthy);
+        assert_eq!(state.stats().last_error, Some(ProbeError::HttpStatus(502)));
+
+        // A single timeout weighs as much as two failures with the default policy.
+        state.record(Err(ProbeError::Timeout));
+        assert!(state.is_down());
+    }
+
+    #[test]
+    fn test_rate_limiting_only_cools_down() {
+        let mut state = ProviderState::new(&SelectorConfig {
+            circuit_breaker: Some(CircuitBreakerPolicy {
+                consecutive_failures: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        state.record(Ok(Duration::from_millis(10)));
+        state.record(Err(ProbeError::RateLimited { retry_after: None }));
+        state.record_passive(Err(ProbeError::RateLimited {
+            retry_after: Some(Duration::from_secs(5)),
+        }));
+
+        assert!(state.cooldown().is_some());
+        assert_eq!(state.health(), ProviderHealth::Healthy);
+        assert!(!state.is_down());
+        assert_eq!(state.circuit(), CircuitState::Closed);
+        assert_eq!(
+            state.stats().last_error,
+            Some(ProbeError::RateLimited {
+                retry_after: Some(Duration::from_secs(5))
+            })
+        );
+    }
+
+    #[test]
+    fn test_passive_samples_are_blended() {
+        let mut state = ProviderState::new(&SelectorConfig {
+            passive_weight: 0.25,
+            ..Default::default()
+        });
+        state.record_passive(Ok(Duration::from_millis(100)));
+        let stats = state.stats();
+        assert_eq!(stats.last, Some(Duration::from_millis(100)));
+        assert_eq!(stats.samples, 0);
+        assert_eq!(stats.passive_samples, 1);
+
+        state.record(Ok(Duration::from_millis(20)));
+        let stats = state.stats();
+        assert_eq!(stats.ewma, Some(Duration::from_millis(40)));
+        assert_eq!(stats.passive_latency, Some(Duration::from_millis(100)));
+
+        // Percentiles are not blended, those of real requests are reported separately.
+        assert_eq!(stats.p95, Some(Duration::from_millis(20)));
+        assert_eq!(stats.passive_p95, Some(Duration::from_millis(100)));
+
+        // Failed requests count towards the health of the provider.
+        state.record_passive(Err(ProbeError::HttpStatus(503)));
+        state.record_passive(Err(ProbeError::HttpStatus(503)));
+        assert!(state.is_down());
+        assert_eq!(state.stats().samples, 1);
+
+        // Passive samples are ignored with a weight of zero.
+        let mut state = ProviderState::new(&SelectorConfig {
+            passive_weight: 0.0,
+            ..Default::default()
+        });
+        state.record_passive(Ok(Duration::from_millis(100)));
+        assert_eq!(state.stats().ewma, None);
+    }
+}
@@ -0,0 +1,147 @@
+// Standard library modules
+use std::time::Duration;
+
+// Internal modules
+use crate::strategy::{Ranking, SelectionStrategy};
+
+/// Keeps the selected provider until the selection strategy consistently prefers a challenger, so that
+/// near-equal providers do not alternate on every round.
+///
+/// The challenger is the provider the strategy prefers over time (see `SelectionStrategy::preferred`), i.e.
+/// the fastest one unless the strategy prefers another one, e.g. because of a priority tier. Challengers faster
+/// than the selected provider must beat it by the margin, while slower ones only have to be preferred for the
+/// number of rounds.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{SelectorConfig, Stickiness, SwitchMargin};
+///
+/// // Switch only after a provider is at least 20% faster for 3 consecutive rounds.
+/// let config = SelectorConfig {
+///     stickiness: Some(Stickiness::new(SwitchMargin::Percentage(20.0), 3)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stickiness {
+    /// The margin by which a challenger must beat the current provider.
+    pub margin: SwitchMargin,
+
+    /// The number of consecutive rounds a challenger must beat the current provider before it is selected.
+    pub rounds: usize,
+}
+
+impl Stickiness {
+    /// Creates a stickiness policy switching after a challenger beats the current provider by `margin` for
+    /// `rounds` consecutive rounds.
+    pub fn new(margin: SwitchMargin, rounds: usize) -> Self {
+        Stickiness { margin, rounds }
+    }
+}
+
+/// The margin by which a challenger must beat the current provider.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchMargin {
+    /// The challenger must be faster by at least the given duration.
+    Absolute(Duration),
+
+    /// The challenger must be faster by at least the given percentage of the latency of the current provider.
+    Percentage(f64),
+}
+
+impl SwitchMargin {
+    /// Checks whether a provider answering in `challenger` beats one answering in `current` by the margin.
+    fn is_beaten(&self, current: Duration, challenger: Duration) -> bool {
+        match *self {
+            SwitchMargin::Absolute(margin) => challenger.saturating_add(margin) <= current,
+            SwitchMargin::Percentage(percentage) => {
+                challenger.as_secs_f64() <= current.as_secs_f64() * (1.0 - percentage / 100.0)
+            }
+        }
+    }
+}
+
+/// The provider kept selected by a stickiness policy, along with its current challenger.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StickySelection {
+    /// The URL of the selected provider.
+    current: Option<String>,
+
+    /// The URL of the provider beating the selected one, along with the number of consecutive rounds it did.
+    challenger: Option<(String, usize)>,
+}
+
+impl StickySelection {
+    /// Returns the URL of the selected provider, if any.
+    pub(crate) fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Updates the selection with the ranking of a round.
+    ///
+    /// The provider preferred by the `strategy` is selected right away if the current one is no longer
+    /// eligible, and otherwise once the strategy preferred it for the configured number of consecutive rounds.
+    /// A challenger faster than the current provider must also beat it by the margin, while a slower one only
+    /// has to hold for the rounds.
+    pub(crate) fn update(
+        &mut self,
+        ranking: &Ranking,
+        stickiness: &Stickiness,
+        strategy: &dyn SelectionStrategy,
+    ) {
+        let Some(preferred) = ranking.preferred(strategy) else {
+            return;
+        };
+
+        // Select the preferred provider if the current one cannot be kept.
+        let current_latency = self.current.as_deref().and_then(|url| ranking.latency(url));
+        let Some(current_latency) = current_latency else {
+            self.current = Some(preferred.to_string());
+            self.challenger = None;
+            return;
+        };
+
+        // Count the consecutive rounds the preferred provider challenges the current one.
+        let preferred_latency = ranking.latency(preferred).unwrap_or(Duration::MAX);
+        if self.current.as_deref() == Some(preferred)
+            || (preferred_latency < current_latency
+                && !stickiness
+                    .margin
+                    .is_beaten(current_latency, preferred_latency))
+        {
+            self.challenger = None;
+            return;
+        }
+        let rounds = match self.challenger.take() {
+            Some((url, rounds)) if url == preferred => rounds + 1,
+            _ => 1,
+        };
+
+        // Switch once the challenger held for enough rounds.
+        if rounds >= stickiness.rounds {
+            self.current = Some(preferred.to_string());
+        } else {
+            self.challenger = Some((preferred.to_string(), rounds));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sticky::SwitchMargin;
+    use std::time::Duration;
+
+    #[test]
+    fn test_switch_margin() {
+        let current = Duration::from_millis(100);
+
+        let absolute = SwitchMargin::Absolute(Duration::from_millis(10));
+        assert!(absolute.is_beaten(current, Duration::from_millis(90)));
+        assert!(!absolute.is_beaten(current, Duration::from_millis(95)));
+
+        let percentage = SwitchMargin::Percentage(20.0);
+        assert!(percentage.is_beaten(current, Duration::from_millis(75)));
+        assert!(!percentage.is_beaten(current, Duration::from_millis(85)));
+    }
+}
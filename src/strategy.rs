@@ -0,0 +1,410 @@
+// Standard library modules
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+    time::Duration,
+};
+
+// Internal modules
+use crate::{
+    config::SelectorConfig,
+    stats::{ProviderHealth, ProviderState, ProviderStats},
+};
+
+/// A provider eligible for selection, along with its statistics.
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    /// The URL of the provider.
+    pub url: &'a str,
+
+    /// The rolling statistics of the provider.
+    pub stats: &'a ProviderStats,
+
+    /// The latency the provider is ranked by, according to the configured selection statistic.
+    pub latency: Duration,
+}
+
+/// Chooses the provider returned by `get_fastest_provider` among the eligible candidates.
+///
+/// Quarantined and down providers are never passed to the strategy, and providers that lag behind the chain
+/// head or whose latest probe failed are only passed when no better candidate exists.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{Candidate, SelectionStrategy};
+///
+/// /// Always prefers the second fastest provider, e.g. to keep the fastest one for other services.
+/// #[derive(Debug)]
+/// struct SecondFastest;
+///
+/// impl SelectionStrategy for SecondFastest {
+///     fn select(&self, candidates: &[Candidate<'_>]) -> usize {
+///         candidates.len().min(2) - 1
+///     }
+/// }
+/// ```
+pub trait SelectionStrategy: fmt::Debug + Send + Sync {
+    /// Returns the index of the chosen candidate.
+    ///
+    /// `candidates` is never empty and is sorted by ascending latency. Out of range indexes select the last
+    /// candidate.
+    fn select(&self, candidates: &[Candidate<'_>]) -> usize;
+
+    /// Returns the index of the candidate the strategy prefers over time, which a sticky selection switches to
+    /// once it consistently prefers another provider. Defaults to the fastest candidate.
+    ///
+    /// Unlike `select`, this is called once per round rather than once per selection, so it must be
+    /// deterministic and must not change the state of the strategy.
+    fn preferred(&self, _candidates: &[Candidate<'_>]) -> usize {
+        0
+    }
+}
+
+/// Selects the provider with the lowest latency. This is the default strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LowestLatency;
+
+impl SelectionStrategy for LowestLatency {
+    fn select(&self, _candidates: &[Candidate<'_>]) -> usize {
+        0
+    }
+}
+
+/// Selects a random provider, with a probability proportional to the inverse of its latency.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeightedRandom;
+
+impl SelectionStrategy for WeightedRandom {
+    fn select(&self, candidates: &[Candidate<'_>]) -> usize {
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|candidate| 1.0 / candidate.latency.as_secs_f64().max(1e-6))
+            .collect();
+        let total: f64 = weights.iter().sum();
+
+        // Walk the cumulative weights until the random point is reached.
+        let mut point = fastrand::f64() * total;
+        for (index, weight) in weights.iter().enumerate() {
+            if point < *weight {
+                return index;
+            }
+            point -= weight;
+        }
+        candidates.len() - 1
+    }
+}
+
+/// Picks two providers at random and selects the one with the lower latency.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerOfTwoChoices;
+
+impl SelectionStrategy for PowerOfTwoChoices {
+    fn select(&self, candidates: &[Candidate<'_>]) -> usize {
+        if candidates.len() < 2 {
+            return 0;
+        }
+
+        // Draw two distinct candidates, the one with the lower index is the faster one.
+        let first = fastrand::usize(..candidates.len());
+        let second = (first + fastrand::usize(1..candidates.len())) % candidates.len();
+        first.min(second)
+    }
+}
+
+/// Rotates over the `k` providers with the lowest latency.
+#[derive(Debug, Default)]
+pub struct RoundRobinTopK {
+    /// The number of fastest providers to rotate over.
+    k: usize,
+
+    /// The number of selections made so far.
+    counter: AtomicUsize,
+}
+
+impl RoundRobinTopK {
+    /// Creates a strategy rotating over the `k` fastest providers.
+    pub fn new(k: usize) -> Self {
+        RoundRobinTopK {
+            k: k.max(1),
+            counter: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SelectionStrategy for RoundRobinTopK {
+    fn select(&self, candidates: &[Candidate<'_>]) -> usize {
+        self.counter.fetch_add(1, Ordering::Relaxed) % self.k.min(candidates.len())
+    }
+}
+
+/// Selects among the providers of the first tier with an eligible provider, using another strategy
+/// within the tier.
+///
+/// Providers that are not listed in any tier form an implicit last tier.
+///
+/// # Example
+///
+/// ```
+/// use web3_closest_provider::{PriorityTiers, WeightedRandom};
+///
+/// let strategy = PriorityTiers::new(vec![
+///     vec!["ipc:///var/run/geth.ipc".to_string()],
+///     vec!["https://eth.llamarpc.com".to_string(), "https://rpc.ankr.com/eth".to_string()],
+/// ])
+/// .within(WeightedRandom);
+/// ```
+#[derive(Debug)]
+pub struct PriorityTiers {
+    /// The URLs of the providers in each tier, highest priority first.
+    tiers: Vec<Vec<String>>,
+
+    /// The strategy used to select a provider within a tier.
+    within: Arc<dyn SelectionStrategy>,
+}
+
+impl PriorityTiers {
+    /// Creates a strategy preferring the providers of the first tiers, selecting the provider with the
+    /// lowest latency within a tier.
+    pub fn new(tiers: Vec<Vec<String>>) -> Self {
+        PriorityTiers {
+            tiers,
+            within: Arc::new(LowestLatency),
+        }
+    }
+
+    /// Sets the strategy used to select a provider within a tier.
+    pub fn within(mut self, strategy: impl SelectionStrategy + 'static) -> Self {
+        self.within = Arc::new(strategy);
+        self
+    }
+
+    /// Returns the tier of the provider at `url`.
+    fn tier(&self, url: &str) -> usize {
+        self.tiers
+            .iter()
+            .position(|tier| tier.iter().any(|tier_url| tier_url == url))
+            .unwrap_or(self.tiers.len())
+    }
+
+    /// Returns the first tier with a candidate.
+    fn best_tier(&self, candidates: &[Candidate<'_>]) -> usize {
+        candidates
+            .iter()
+            .map(|candidate| self.tier(candidate.url))
+            .min()
+            .unwrap_or_default()
+    }
+}
+
+impl SelectionStrategy for PriorityTiers {
+    fn select(&self, candidates: &[Candidate<'_>]) -> usize {
+        let best_tier = self.best_tier(candidates);
+
+        // Select within the best tier, keeping track of the original indexes.
+        let (indexes, tier_candidates): (Vec<usize>, Vec<Candidate<'_>>) = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| self.tier(candidate.url) == best_tier)
+            .map(|(index, candidate)| (index, *candidate))
+            .unzip();
+        let selected = self.within.select(&tier_candidates);
+
+        indexes[selected.min(indexes.len() - 1)]
+    }
+
+    fn preferred(&self, candidates: &[Candidate<'_>]) -> usize {
+        // Prefer the fastest candidate of the best tier.
+        let best_tier = self.best_tier(candidates);
+        candidates
+            .iter()
+            .position(|candidate| self.tier(candidate.url) == best_tier)
+            .unwrap_or_default()
+    }
+}
+
+/// The providers eligible for selection, sorted by ascending latency.
+///
+/// Quarantined and down providers, providers that never answered successfully within their sample window,
+/// providers with an open circuit and providers cooling down after rate limiting the balancer are never eligible. The eligible providers are the healthy ones that keep up
+/// with the chain head, or the lagging ones if there are none, or the degraded ones otherwise.
+pub(crate) struct Ranking {
+    /// The URL, statistics and ranking latency of each eligible provider.
+    providers: Vec<(String, ProviderStats, Duration)>,
+
+    /// The URLs of the providers that are available but not eligible, sorted by tier then
+    /// by ascending latency.
+    fallbacks: Vec<String>,
+}
+
+impl Ranking {
+    /// Ranks the providers of `provider_states` according to `config`.
+    pub(crate) fn new(
+        provider_states: &HashMap<String, ProviderState>,
+        config: &SelectorConfig,
+    ) -> Self {
+        // Find the highest block number observed across the providers on the expected chain that are not down, so
+        // that a provider on another chain does not make every other provider look lagging.
+        let head = provider_states
+            .values()
+            .filter(|state| !state.is_quarantined(config) && !state.is_down())
+            .filter_map(|state| state.block_number)
+            .max();
+
+        // Compute the statistics and the tier of every available provider.
+        let ranked: Vec<(&String, ProviderStats, u8)> = provider_states
+            .iter()
+            .filter(|(_, state)| state.is_available(config))
+            .map(|(url, state)| {
+                let stats = state.stats();
+                let tier = if stats.health != ProviderHealth::Healthy {
+                    2
+                } else if state.is_lagging(config, head) {
+                    1
+                } else {
+                    0
+                };
+                (url, stats, tier)
+            })
+            .collect();
+        let best_tier = ranked.iter().map(|(_, _, tier)| *tier).min();
+
+        // Keep the providers of the best tier, sorted by ascending latency, and the others as fallbacks.
+        let (mut providers, mut others): (Vec<_>, Vec<_>) = ranked
+            .into_iter()
+            .map(|(url, stats, tier)| {
+                let latency = stats
+                    .latency(config.selection_statistic)
+                    .unwrap_or(Duration::MAX);
+                (url.clone(), stats, latency, tier)
+            })
+            .partition(|(_, _, _, tier)| Some(*tier) == best_tier);
+        providers.sort_by_key(|(_, _, latency, _)| *latency);
+        others.sort_by_key(|(_, _, latency, tier)| (*tier, *latency));
+
+        Ranking {
+            providers: providers
+                .into_iter()
+                .map(|(url, stats, latency, _)| (url, stats, latency))
+                .collect(),
+            fallbacks: others.into_iter().map(|(url, _, _, _)| url).collect(),
+        }
+    }
+
+    /// Returns the ranking latency of the provider at `url`, `None` if it is not eligible.
+    pub(crate) fn latency(&self, url: &str) -> Option<Duration> {
+        self.providers
+            .iter()
+            .find(|(provider_url, _, _)| provider_url == url)
+            .map(|(_, _, latency)| *latency)
+    }
+
+    /// Checks whether the provider at `url` is eligible.
+    pub(crate) fn contains(&self, url: &str) -> bool {
+        self.latency(url).is_some()
+    }
+
+    /// Returns the URLs of the providers to try in order when sending a request, starting with `first`, then
+    /// the other eligible providers and the fallbacks.
+    pub(crate) fn failover_order(&self, first: &str) -> Vec<String> {
+        let mut order = vec![first.to_string()];
+        order.extend(
+            self.providers
+                .iter()
+                .map(|(url, _, _)| url)
+                .chain(&self.fallbacks)
+                .filter(|url| *url != first)
+                .cloned(),
+        );
+        order
+    }
+
+    /// Returns the URL of the provider chosen by `strategy`, `None` if no provider is eligible.
+    pub(crate) fn select(&self, strategy: &dyn SelectionStrategy) -> Option<&str> {
+        self.choose(|candidates| strategy.select(candidates))
+    }
+
+    /// Returns the URL of the provider preferred by `strategy`, `None` if no provider is eligible.
+    pub(crate) fn preferred(&self, strategy: &dyn SelectionStrategy) -> Option<&str> {
+        self.choose(|candidates| strategy.preferred(candidates))
+    }
+
+    /// Returns the URL of the candidate at the index returned by `choose`, `None` if no provider is eligible.
+    fn choose(&self, choose: impl FnOnce(&[Candidate<'_>]) -> usize) -> Option<&str> {
+        if self.providers.is_empty() {
+            return None;
+        }
+
+        let candidates: Vec<Candidate<'_>> = self
+            .providers
+            .iter()
+            .map(|(url, stats, latency)| Candidate {
+                url,
+                stats,
+                latency: *latency,
+            })
+            .collect();
+        let index = choose(&candidates);
+
+        Some(candidates[index.min(candidates.len() - 1)].url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        stats::SampleWindow,
+        strategy::{
+            Candidate, PowerOfTwoChoices, PriorityTiers, RoundRobinTopK, SelectionStrategy,
+            WeightedRandom,
+        },
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_built_in_strategies() {
+        let stats = SampleWindow::new(1, 0.5).stats(None, None);
+        let urls = [
+            "https://a.example",
+            "https://b.example",
+            "https://c.example",
+        ];
+        let candidates: Vec<Candidate<'_>> = urls
+            .iter()
+            .enumerate()
+            .map(|(index, url)| Candidate {
+                url,
+                stats: &stats,
+                latency: Duration::from_millis(10 * (index as u64 + 1)),
+            })
+            .collect();
+
+        let round_robin = RoundRobinTopK::new(2);
+        let selected: Vec<usize> = (0..4).map(|_| round_robin.select(&candidates)).collect();
+        assert_eq!(selected, vec![0, 1, 0, 1]);
+
+        let tiers = PriorityTiers::new(vec![vec![urls[2].to_string()]]);
+        assert_eq!(tiers.select(&candidates), 2);
+        let tiers = PriorityTiers::new(vec![vec!["https://down.example".to_string()]]);
+        assert_eq!(tiers.select(&candidates), 0);
+
+        // The preferred candidate does not depend on the state of the strategy, nor change it.
+        let round_robin = RoundRobinTopK::new(2);
+        assert_eq!(round_robin.preferred(&candidates), 0);
+        assert_eq!(round_robin.preferred(&candidates), 0);
+        assert_eq!(round_robin.select(&candidates), 0);
+        let tiers = PriorityTiers::new(vec![vec![urls[1].to_string(), urls[2].to_string()]])
+            .within(RoundRobinTopK::new(2));
+        assert_eq!(tiers.preferred(&candidates), 1);
+        assert_eq!(tiers.preferred(&candidates), 1);
+        assert_eq!(tiers.select(&candidates), 1);
+        assert_eq!(WeightedRandom.preferred(&candidates), 0);
+
+        for _ in 0..100 {
+            assert!(WeightedRandom.select(&candidates) < candidates.len());
+            assert!(PowerOfTwoChoices.select(&candidates) < candidates.len() - 1);
+        }
+    }
+}
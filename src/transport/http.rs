@@ -0,0 +1,288 @@
+// Standard library modules
+use std::{
+    error::Error,
+    io,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+// External libraries
+use hyper::{
+    body::to_bytes,
+    client::conn::handshake,
+    header::{CONTENT_TYPE, HOST, RETRY_AFTER},
+    Body, Request,
+};
+use reqwest::{header::HeaderMap, StatusCode, Url};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{lookup_host, TcpStream},
+    sync::OnceCell,
+    time::timeout,
+};
+use tokio_native_tls::{native_tls, TlsConnector};
+
+// Internal modules
+use super::{classify_io_error, error_chain, parse_retry_after, JsonRpcResponse, TimedResponse};
+use crate::{error::ProbeError, stats::LatencyPhases};
+
+/// Sends JSON-RPC requests to a provider as HTTP POST requests.
+pub(crate) struct HttpTransport {
+    /// The URL of the provider.
+    url: String,
+
+    /// The maximum time spent establishing a new connection.
+    connect_timeout: Duration,
+
+    /// The headers sent with every request.
+    headers: HeaderMap,
+
+    /// The TLS connector of cold requests, built by the first request to an HTTPS provider.
+    tls_connector: OnceCell<TlsConnector>,
+}
+
+impl HttpTransport {
+    /// Creates the transport of the provider at `url`, sending the `headers` with every request.
+    pub(crate) fn new(url: &str, connect_timeout: Duration, headers: HeaderMap) -> Self {
+        HttpTransport {
+            url: url.to_string(),
+            connect_timeout,
+            headers,
+            tls_connector: OnceCell::new(),
+        }
+    }
+
+    /// Sends a JSON-RPC request through the pooled connections of `client` and returns the response along
+    /// with its timings.
+    ///
+    /// The response time ends once the response headers are received. Connection phases are not broken
+    /// down, the time to first byte includes them if no idle connection was available.
+    pub(crate) async fn request(
+        &self,
+        client: &reqwest::Client,
+        body: &Value,
+    ) -> Result<TimedResponse, ProbeError> {
+        // Record the start time of the request.
+        let start_time = Instant::now();
+
+        // Send the request and handle potential errors.
+        let response = client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .json(body)
+            .send()
+            .await
+            .map_err(classify_reqwest_error)?;
+
+        // Record the end time of the request.
+        let end_time = Instant::now();
+
+        // Read and parse the JSON-RPC response.
+        check_status(response.status(), response.headers())?;
+        let bytes = response.bytes().await.map_err(classify_reqwest_error)?;
+        let body_time = Instant::now();
+        let json_response: JsonRpcResponse =
+            serde_json::from_slice(&bytes).map_err(|e| ProbeError::MalformedJson(e.to_string()))?;
+
+        Ok(TimedResponse {
+            response_time: end_time.duration_since(start_time),
+            phases: Some(LatencyPhases {
+                dns: None,
+                connect: None,
+                tls: None,
+                ttfb: end_time.duration_since(start_time),
+                body: body_time.duration_since(end_time),
+            }),
+            response: json_response,
+        })
+    }
+
+    /// Sends a JSON-RPC request over a new connection and returns the response along with the time spent in
+    /// each phase of the request.
+    ///
+    /// The response time covers the DNS lookup, the TCP and TLS handshakes and the time to first byte. The
+    /// connection is opened without the injected HTTP client, so its proxies, root certificates and identity are
+    /// not used.
+    pub(crate) async fn request_cold(&self, body: &Value) -> Result<TimedResponse, ProbeError> {
+        let url = Url::parse(&self.url).map_err(|e| ProbeError::Connection(e.to_string()))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| ProbeError::Connection(format!("Missing host in {}", url)))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        // Resolve the host name, unless the URL contains an IP address.
+        let start_time = Instant::now();
+        let (address, dns) = match host.parse::<IpAddr>() {
+            Ok(ip) => (SocketAddr::new(ip, port), None),
+            Err(_) => {
+                let address = lookup_host((host.as_str(), port))
+                    .await
+                    .map_err(|e| ProbeError::Dns(e.to_string()))?
+                    .next()
+                    .ok_or_else(|| ProbeError::Dns(format!("No address found for {}", host)))?;
+                (address, Some(start_time.elapsed()))
+            }
+        };
+
+        // Open the TCP connection.
+        let connect_start = Instant::now();
+        let stream = timeout(self.connect_timeout, TcpStream::connect(address))
+            .await
+            .map_err(|_| ProbeError::Timeout)?
+            .map_err(|e| classify_io_error(&e))?;
+        let connect = connect_start.elapsed();
+
+        // Perform the TLS handshake for HTTPS providers, then send the request.
+        let (tls, (ttfb, body, response)) = if url.scheme() == "https" {
+            let connector = self
+                .tls_connector
+                .get_or_try_init(|| async {
+                    native_tls::TlsConnector::new()
+                        .map(TlsConnector::from)
+                        .map_err(|e| ProbeError::Tls(error_chain(&e)))
+                })
+                .await?;
+            let tls_start = Instant::now();
+            let stream = timeout(self.connect_timeout, connector.connect(&host, stream))
+                .await
+                .map_err(|_| ProbeError::Timeout)?
+                .map_err(|e| ProbeError::Tls(error_chain(&e)))?;
+            (
+                Some(tls_start.elapsed()),
+                exchange(stream, &url, &self.headers, body).await?,
+            )
+        } else {
+            (None, exchange(stream, &url, &self.headers, body).await?)
+        };
+
+        Ok(TimedResponse {
+            response_time: dns.unwrap_or_default() + connect + tls.unwrap_or_default() + ttfb,
+            phases: Some(LatencyPhases {
+                dns,
+                connect: Some(connect),
+                tls,
+                ttfb,
+                body,
+            }),
+            response,
+        })
+    }
+}
+
+/// Sends a JSON-RPC request over an established connection and returns the time to first byte, the time
+/// spent reading the body and the response.
+async fn exchange<S>(
+    stream: S,
+    url: &Url,
+    headers: &HeaderMap,
+    body: &Value,
+) -> Result<(Duration, Duration, JsonRpcResponse), ProbeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let connection_error = |e: hyper::Error| ProbeError::Connection(error_chain(&e));
+
+    // Drive the HTTP connection in the background until the response is read.
+    let (mut sender, connection) = handshake(stream).await.map_err(connection_error)?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    // Build the request with the path and host of the provider.
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let mut request = Request::post(path)
+        .header(HOST, host)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|e| ProbeError::Connection(e.to_string()))?;
+    request.headers_mut().extend(headers.clone());
+
+    // Send the request, then read the response headers and body.
+    let sent_at = Instant::now();
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(connection_error)?;
+    let headers_at = Instant::now();
+    check_status(response.status(), response.headers())?;
+    let bytes = to_bytes(response.into_body())
+        .await
+        .map_err(connection_error)?;
+    let body_at = Instant::now();
+
+    // Parse the JSON-RPC response.
+    let json_response: JsonRpcResponse =
+        serde_json::from_slice(&bytes).map_err(|e| ProbeError::MalformedJson(e.to_string()))?;
+
+    Ok((
+        headers_at.duration_since(sent_at),
+        body_at.duration_since(headers_at),
+        json_response,
+    ))
+}
+
+/// Rejects unsuccessful status codes, along with the `Retry-After` header of rate limiting responses.
+fn check_status(status: StatusCode, headers: &HeaderMap) -> Result<(), ProbeError> {
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(ProbeError::RateLimited {
+            retry_after: headers
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after),
+        });
+    }
+    if !status.is_success() {
+        return Err(ProbeError::HttpStatus(status.as_u16()));
+    }
+    Ok(())
+}
+
+/// Builds the client probing HTTP providers over pooled connections, which are kept open between rounds.
+pub(crate) fn warm_client(connect_timeout: Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(None)
+        .tcp_keepalive(Duration::from_secs(30))
+        .build()
+        .expect("Failed to create the HTTP client")
+}
+
+/// Classifies an error raised by `reqwest` by walking its sources.
+fn classify_reqwest_error(error: reqwest::Error) -> ProbeError {
+    if error.is_timeout() {
+        return ProbeError::Timeout;
+    }
+
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let message = cause.to_string();
+        let lowercase = message.to_lowercase();
+        if lowercase.contains("dns error") {
+            return ProbeError::Dns(error_chain(cause));
+        }
+        if lowercase.contains("tls")
+            || lowercase.contains("ssl")
+            || lowercase.contains("certificate")
+        {
+            return ProbeError::Tls(error_chain(cause));
+        }
+        if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+            return classify_io_error(io_error);
+        }
+        source = cause.source();
+    }
+
+    ProbeError::Connection(error_chain(&error))
+}
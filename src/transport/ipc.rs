@@ -0,0 +1,148 @@
+// Standard library modules
+use std::{path::PathBuf, time::Duration};
+
+#[cfg(unix)]
+use std::{io, time::Instant};
+
+// External libraries
+#[cfg(unix)]
+use serde_json::Value;
+use tokio::sync::mpsc;
+#[cfg(unix)]
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    time::{sleep_until, timeout},
+};
+
+// Internal modules
+use super::persistent::{PersistentConnection, QueuedRequest};
+#[cfg(unix)]
+use super::{
+    classify_io_error,
+    persistent::{Backoff, PendingRequests},
+};
+use crate::error::ProbeError;
+
+/// Creates a persistent IPC connection to the Unix domain socket of a local node at `path`.
+///
+/// Requests are written as JSON documents and responses are read back from the byte stream as they complete,
+/// whether or not the node separates them with newlines. Connection attempts are abandoned after
+/// `connect_timeout`. Requests fail after waiting `response_timeout` for their response, and the connection is
+/// re-established if nothing was received since they were sent.
+pub(crate) fn connect(
+    path: PathBuf,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+) -> PersistentConnection {
+    PersistentConnection::spawn(move |requests| {
+        run(path, connect_timeout, response_timeout, requests)
+    })
+}
+
+/// Keeps a connection to the socket at `path` open and forwards requests received on `requests` over it.
+#[cfg(unix)]
+async fn run(
+    path: PathBuf,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+    mut requests: mpsc::UnboundedReceiver<QueuedRequest>,
+) {
+    let mut backoff = Backoff::new();
+
+    loop {
+        // Connect to the socket, rejecting requests while waiting for the next attempt on failure.
+        let connected = timeout(connect_timeout, UnixStream::connect(&path))
+            .await
+            .unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::TimedOut)));
+        let stream = match connected {
+            Ok(stream) => {
+                backoff.reset();
+                stream
+            }
+            Err(e) => {
+                if !backoff.wait(&mut requests, &classify_io_error(&e)).await {
+                    return;
+                }
+                continue;
+            }
+        };
+        let (mut reader, mut writer) = stream.into_split();
+        let mut pending = PendingRequests::new(response_timeout);
+        let mut buffer = Vec::new();
+
+        loop {
+            let deadline = pending.deadline();
+            tokio::select! {
+                // Send a request with a unique id over the connection.
+                request = requests.recv() => {
+                    let Some(mut request) = request else {
+                        return;
+                    };
+                    let Some((id, mut body)) = pending.prepare(&mut request) else {
+                        continue;
+                    };
+                    body.push('\n');
+
+                    let sent_at = Instant::now();
+                    let sent = writer.write_all(body.as_bytes()).await;
+                    pending.insert(id, sent_at, request);
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+
+                // Dispatch every complete response read from the connection.
+                read = reader.read_buf(&mut buffer) => {
+                    if !matches!(read, Ok(n) if n > 0) {
+                        break;
+                    }
+                    let received_at = Instant::now();
+                    pending.received(received_at);
+
+                    let mut responses = serde_json::Deserializer::from_slice(&buffer).into_iter::<Value>();
+                    let mut consumed = 0;
+                    let malformed = loop {
+                        match responses.next() {
+                            Some(Ok(response)) => {
+                                pending.resolve(response, received_at);
+                                consumed = responses.byte_offset();
+                            }
+                            Some(Err(e)) => break !e.is_eof(),
+                            None => break false,
+                        }
+                    };
+                    if malformed {
+                        break;
+                    }
+                    buffer.drain(..consumed);
+                }
+
+                // Fail the timed out requests, and reconnect if the node stopped answering.
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                    if pending.expire(Instant::now()) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Fail the requests lost with the connection before reconnecting.
+        pending.fail_all(&ProbeError::Connection("IPC connection closed".to_string()));
+    }
+}
+
+/// Fails every request, IPC connections are only supported on Unix platforms.
+#[cfg(not(unix))]
+async fn run(
+    _path: PathBuf,
+    _connect_timeout: Duration,
+    _response_timeout: Duration,
+    mut requests: mpsc::UnboundedReceiver<QueuedRequest>,
+) {
+    while let Some(request) = requests.recv().await {
+        let _ = request.response.send(Err(ProbeError::Connection(
+            "IPC providers are only supported on Unix platforms".to_string(),
+        )));
+    }
+}
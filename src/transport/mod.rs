@@ -0,0 +1,336 @@
+// Standard library modules
+use std::{
+    collections::HashMap,
+    error::Error,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+// External libraries
+use serde::Deserialize;
+use serde_json::Value;
+
+// Internal modules
+mod http;
+mod ipc;
+mod persistent;
+mod ws;
+
+use crate::{config::SelectorConfig, error::ProbeError, stats::LatencyPhases};
+use http::{warm_client, HttpTransport};
+use persistent::PersistentConnection;
+
+/// The JSON-RPC error code providers answer with when they rate limit the caller.
+///
+/// The `-32005` "limit exceeded" code is not one of them: providers also answer with it when a query exceeds a
+/// limit, such as `eth_getLogs` returning too many results, so only its message or data tell a rate limit apart.
+const RATE_LIMIT_CODE: i64 = 429;
+
+/// Lowercase fragments of the JSON-RPC error messages providers answer with when they rate limit the caller.
+const RATE_LIMIT_MESSAGES: [&str; 5] = [
+    "rate limit",
+    "request rate",
+    "too many requests",
+    "request limit",
+    "compute units per second",
+];
+
+/// Represents a JSON-RPC response with an optional result and error field.
+#[derive(Debug, Deserialize)]
+pub(crate) struct JsonRpcResponse {
+    /// Optional result of the call.
+    pub(crate) result: Option<Value>,
+
+    /// Optional error message or object.
+    pub(crate) error: Option<Value>,
+}
+
+impl JsonRpcResponse {
+    /// Returns the result of the call, `null` if missing, or the JSON-RPC error the provider answered with.
+    ///
+    /// Errors telling that the provider rate limits the caller, through their code, message or rate data, are
+    /// reported as `ProbeError::RateLimited` along with the backoff the provider asked for, if any.
+    pub(crate) fn into_result(self) -> Result<Value, ProbeError> {
+        let Some(error) = self.error else {
+            return Ok(self.result.unwrap_or(Value::Null));
+        };
+
+        let code = error
+            .get("code")
+            .and_then(Value::as_i64)
+            .unwrap_or_default();
+        let message = match error.get("message").and_then(Value::as_str) {
+            Some(message) => message.to_string(),
+            None => error.to_string(),
+        };
+        if code == RATE_LIMIT_CODE
+            || error.pointer("/data/rate").is_some()
+            || RATE_LIMIT_MESSAGES
+                .iter()
+                .any(|pattern| message.to_lowercase().contains(pattern))
+        {
+            return Err(ProbeError::RateLimited {
+                retry_after: error
+                    .pointer("/data/rate/backoff_seconds")
+                    .and_then(Value::as_f64)
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()),
+            });
+        }
+        Err(ProbeError::JsonRpc { code, message })
+    }
+}
+
+/// A JSON-RPC response along with the time it took to receive it.
+pub(crate) struct TimedResponse {
+    /// The time from starting the request to receiving the response headers, or the whole response over
+    /// persistent connections.
+    pub(crate) response_time: Duration,
+
+    /// The time spent in each phase of the request, for HTTP providers.
+    pub(crate) phases: Option<LatencyPhases>,
+
+    /// The JSON-RPC response.
+    pub(crate) response: JsonRpcResponse,
+}
+
+/// The connection used to send JSON-RPC requests to a provider, chosen from the scheme of its URL.
+pub(crate) enum Transport {
+    /// Requests are sent as HTTP POST requests (`http://` and `https://`).
+    Http(HttpTransport),
+
+    /// Requests are sent over a persistent WebSocket connection (`ws://` and `wss://`).
+    WebSocket(PersistentConnection),
+
+    /// Requests are sent over a persistent Unix domain socket connection (`ipc://` and plain socket paths).
+    Ipc(PersistentConnection),
+}
+
+impl Transport {
+    /// Creates the transport of the provider at `url`.
+    ///
+    /// WebSocket and IPC transports start connecting in the background right away, so this has to be called
+    /// from within a Tokio runtime. Their connection attempts are abandoned after the connect timeout of
+    /// `config`, and they are re-established when the provider stops answering for longer than the probe and
+    /// attempt timeouts. The headers of `config` are sent with every HTTP request and WebSocket handshake.
+    pub(crate) fn new(url: &str, config: &SelectorConfig) -> Self {
+        // Persistent connections wait for responses as long as probes and requests may take.
+        let response_timeout = config.probe_timeout.max(config.retry.attempt_timeout);
+
+        if url.starts_with("ws://") || url.starts_with("wss://") {
+            Transport::WebSocket(ws::connect(
+                url,
+                config.connect_timeout,
+                response_timeout,
+                config.headers.clone(),
+            ))
+        } else if let Some(path) = ipc_path(url) {
+            Transport::Ipc(ipc::connect(path, config.connect_timeout, response_timeout))
+        } else {
+            Transport::Http(HttpTransport::new(
+                url,
+                config.connect_timeout,
+                config.headers.clone(),
+            ))
+        }
+    }
+
+    /// Checks whether requests are sent as HTTP requests, which may open a new connection for every request.
+    pub(crate) fn is_http(&self) -> bool {
+        matches!(self, Transport::Http(_))
+    }
+
+    /// Sends a JSON-RPC request and returns the response along with its timings.
+    ///
+    /// HTTP transports send the request through the pooled connections of `client`, or over a new connection
+    /// when `cold` is set. Persistent connections are always reused.
+    pub(crate) async fn request(
+        &self,
+        client: &reqwest::Client,
+        body: &Value,
+        cold: bool,
+    ) -> Result<TimedResponse, ProbeError> {
+        match self {
+            Transport::Http(transport) if cold => transport.request_cold(body).await,
+            Transport::Http(transport) => transport.request(client, body).await,
+            Transport::WebSocket(connection) | Transport::Ipc(connection) => {
+                connection.request(body).await
+            }
+        }
+    }
+}
+
+/// The transports of the providers of a balancer, shared by its prober and the requests sent through it.
+pub(crate) struct TransportPool {
+    /// The client sending the requests to HTTP providers over warm connections.
+    client: reqwest::Client,
+
+    /// The transport used to reach each provider.
+    transports: Mutex<HashMap<String, Arc<Transport>>>,
+}
+
+impl TransportPool {
+    /// Creates an empty pool, reusing the client injected through `config` or creating one that keeps its
+    /// connections warm between requests.
+    pub(crate) fn new(config: &SelectorConfig) -> Self {
+        TransportPool {
+            client: match &config.http_client {
+                Some(client) => client.clone(),
+                None => warm_client(config.connect_timeout),
+            },
+            transports: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the client sending the requests to HTTP providers.
+    pub(crate) fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Returns the transport of the provider at `url`, if it was created.
+    pub(crate) fn get(&self, url: &str) -> Option<Arc<Transport>> {
+        self.transports.lock().unwrap().get(url).cloned()
+    }
+
+    /// Creates the transports of the providers at `urls` that have none yet, and drops the transports of the
+    /// other providers, which closes their persistent connections.
+    pub(crate) fn update(&self, urls: &[String], config: &SelectorConfig) {
+        let mut transports = self.transports.lock().unwrap();
+        transports.retain(|url, _| urls.contains(url));
+        for url in urls {
+            if !transports.contains_key(url) {
+                let transport = Transport::new(url, config);
+                transports.insert(url.clone(), Arc::new(transport));
+            }
+        }
+    }
+}
+
+/// Returns the socket path of an IPC provider, given either as an `ipc://` URL or as a plain filesystem path.
+///
+/// Plain paths must be absolute, or contain a path separator and end in `.ipc`, so that URLs missing their
+/// scheme like `localhost:8545` are not mistaken for socket paths.
+pub(crate) fn ipc_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("ipc://") {
+        return Some(PathBuf::from(path));
+    }
+    if url.contains("://") {
+        return None;
+    }
+
+    let path = PathBuf::from(url);
+    let is_socket_path = path.is_absolute()
+        || (url.contains(std::path::MAIN_SEPARATOR) || url.contains('/')) && url.ends_with(".ipc");
+    is_socket_path.then_some(path)
+}
+
+/// Classifies an I/O error raised while connecting to or talking with a provider.
+pub(crate) fn classify_io_error(error: &io::Error) -> ProbeError {
+    let message = error.to_string();
+    match error.kind() {
+        io::ErrorKind::ConnectionRefused => ProbeError::ConnectRefused(message),
+        io::ErrorKind::TimedOut => ProbeError::Timeout,
+        _ if message.contains("lookup address") => ProbeError::Dns(message),
+        _ => ProbeError::Connection(message),
+    }
+}
+
+/// Parses the value of a `Retry-After` header, given either as a number of seconds or as an HTTP date.
+///
+/// Dates in the past yield a zero duration.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Returns the messages of an error and all its sources, separated by colons.
+pub(crate) fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ProbeError,
+        transport::{parse_retry_after, JsonRpcResponse},
+    };
+    use serde_json::json;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let retry_after = parse_retry_after(&date).unwrap();
+        assert!(retry_after > Duration::from_secs(58) && retry_after <= Duration::from_secs(60));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn test_rate_limit_errors_are_classified() {
+        let response = |error| JsonRpcResponse {
+            result: None,
+            error: Some(error),
+        };
+
+        assert_eq!(
+            response(json!({
+                "code": -32005,
+                "message": "daily request count exceeded, request rate limited",
+                "data": {"rate": {"backoff_seconds": 30}}
+            }))
+            .into_result(),
+            Err(ProbeError::RateLimited {
+                retry_after: Some(Duration::from_secs(30))
+            })
+        );
+        assert_eq!(
+            response(json!({"code": -32000, "message": "Too Many Requests"})).into_result(),
+            Err(ProbeError::RateLimited { retry_after: None })
+        );
+        assert_eq!(
+            response(json!({"code": -32005, "message": "project ID request rate exceeded"}))
+                .into_result(),
+            Err(ProbeError::RateLimited { retry_after: None })
+        );
+
+        // The limit exceeded code alone does not tell a rate limit.
+        assert_eq!(
+            response(json!({
+                "code": -32005,
+                "message": "query returned more than 10000 results"
+            }))
+            .into_result(),
+            Err(ProbeError::JsonRpc {
+                code: -32005,
+                message: "query returned more than 10000 results".to_string()
+            })
+        );
+        assert_eq!(
+            response(json!({"code": -32000, "message": "header not found"})).into_result(),
+            Err(ProbeError::JsonRpc {
+                code: -32000,
+                message: "header not found".to_string()
+            })
+        );
+    }
+}
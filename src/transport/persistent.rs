@@ -0,0 +1,280 @@
+// Standard library modules
+use std::{
+    collections::HashMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+// External libraries
+use serde_json::Value;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::sleep,
+};
+
+// Internal modules
+use super::{JsonRpcResponse, TimedResponse};
+use crate::error::ProbeError;
+
+/// The delay before the first reconnection attempt after a failed connection.
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_millis(250);
+
+/// The maximum delay between two reconnection attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Sender of the response time and response of a request to its caller.
+type ResponseSender = oneshot::Sender<Result<(Duration, Value), ProbeError>>;
+
+/// A request waiting to be sent over a persistent connection.
+pub(crate) struct QueuedRequest {
+    /// The JSON-RPC request body, its `id` is replaced by the connection.
+    pub(crate) body: Value,
+
+    /// Sender of the response time and response to the caller.
+    pub(crate) response: ResponseSender,
+}
+
+/// Sends JSON-RPC requests to a provider over a persistent connection owned by a background task.
+///
+/// The task stops once the connection handle is dropped.
+pub(crate) struct PersistentConnection {
+    /// Sender of requests to the connection task.
+    requests: mpsc::UnboundedSender<QueuedRequest>,
+}
+
+impl PersistentConnection {
+    /// Spawns the connection task returned by `run`, which receives the queued requests.
+    pub(crate) fn spawn<F>(run: impl FnOnce(mpsc::UnboundedReceiver<QueuedRequest>) -> F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (requests, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(receiver));
+        PersistentConnection { requests }
+    }
+
+    /// Sends a JSON-RPC request and returns the response along with its response time.
+    ///
+    /// The response time is measured by the connection task between writing the request to the connection
+    /// and reading its response.
+    pub(crate) async fn request(&self, body: &Value) -> Result<TimedResponse, ProbeError> {
+        let (response, receiver) = oneshot::channel();
+        let stopped = || ProbeError::Connection("Connection task stopped".to_string());
+
+        // Hand the request over to the connection task.
+        self.requests
+            .send(QueuedRequest {
+                body: body.clone(),
+                response,
+            })
+            .map_err(|_| stopped())?;
+
+        // Wait for the connection task to receive the response.
+        let (response_time, response) = receiver.await.map_err(|_| stopped())??;
+
+        // Parse the JSON-RPC response.
+        let json_response: JsonRpcResponse = serde_json::from_value(response)
+            .map_err(|e| ProbeError::MalformedJson(e.to_string()))?;
+
+        Ok(TimedResponse {
+            response_time,
+            phases: None,
+            response: json_response,
+        })
+    }
+}
+
+/// The requests sent over a connection which did not receive a response yet, keyed by their `id`.
+///
+/// Requests that did not receive a response within the response timeout are failed and forgotten, so that a
+/// provider that stops answering does not accumulate them.
+pub(crate) struct PendingRequests {
+    /// The `id` assigned to the next request.
+    next_id: u64,
+
+    /// The time each request was sent at and the sender of its response.
+    requests: HashMap<u64, (Instant, ResponseSender)>,
+
+    /// The maximum time a request waits for its response.
+    response_timeout: Duration,
+
+    /// When the latest message was received over the connection, if any.
+    last_received_at: Option<Instant>,
+}
+
+impl PendingRequests {
+    /// Creates an empty set of pending requests waiting at most `response_timeout` for their responses.
+    pub(crate) fn new(response_timeout: Duration) -> Self {
+        PendingRequests {
+            next_id: 1,
+            requests: HashMap::new(),
+            response_timeout,
+            last_received_at: None,
+        }
+    }
+
+    /// Assigns a unique `id` to the request and returns its serialized body.
+    ///
+    /// Returns `None` if the caller stopped waiting for the response, so the request does not need to be sent.
+    pub(crate) fn prepare(&mut self, request: &mut QueuedRequest) -> Option<(u64, String)> {
+        if request.response.is_closed() {
+            return None;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        request.body["id"] = Value::from(id);
+
+        Some((id, request.body.to_string()))
+    }
+
+    /// Registers a request sent at `sent_at` as waiting for its response.
+    pub(crate) fn insert(&mut self, id: u64, sent_at: Instant, request: QueuedRequest) {
+        self.requests.insert(id, (sent_at, request.response));
+    }
+
+    /// Records that a message was received over the connection at `received_at`.
+    pub(crate) fn received(&mut self, received_at: Instant) {
+        self.last_received_at = Some(received_at);
+    }
+
+    /// Dispatches a response received at `received_at` to the request with the same `id`.
+    pub(crate) fn resolve(&mut self, response: Value, received_at: Instant) {
+        self.received(received_at);
+        let id = response.get("id").and_then(Value::as_u64);
+        if let Some((sent_at, sender)) = id.and_then(|id| self.requests.remove(&id)) {
+            let _ = sender.send(Ok((received_at.duration_since(sent_at), response)));
+        }
+    }
+
+    /// Returns when the oldest pending request times out, `None` if no request is pending.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.requests
+            .values()
+            .map(|(sent_at, _)| *sent_at + self.response_timeout)
+            .min()
+    }
+
+    /// Forgets the requests whose caller stopped waiting, and fails those that timed out by `now`.
+    ///
+    /// Returns `true` if the connection looks stalled, i.e. a request timed out although nothing was received
+    /// since it was sent, in which case the connection should be re-established.
+    pub(crate) fn expire(&mut self, now: Instant) -> bool {
+        let mut stalled = false;
+        let timed_out: Vec<u64> = self
+            .requests
+            .iter()
+            .filter(|(_, (sent_at, sender))| {
+                sender.is_closed() || now.duration_since(*sent_at) >= self.response_timeout
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in timed_out {
+            let Some((sent_at, sender)) = self.requests.remove(&id) else {
+                continue;
+            };
+            if now.duration_since(sent_at) >= self.response_timeout {
+                stalled |= self.last_received_at.is_none_or(|at| at < sent_at);
+                let _ = sender.send(Err(ProbeError::Timeout));
+            }
+        }
+        stalled
+    }
+
+    /// Fails all pending requests with `error`, e.g. because the connection was lost.
+    pub(crate) fn fail_all(&mut self, error: &ProbeError) {
+        for (_, (_, sender)) in self.requests.drain() {
+            let _ = sender.send(Err(error.clone()));
+        }
+    }
+}
+
+/// An exponential backoff between reconnection attempts.
+pub(crate) struct Backoff {
+    /// The delay before the next attempt.
+    delay: Duration,
+}
+
+impl Backoff {
+    /// Creates a backoff starting at the initial delay.
+    pub(crate) fn new() -> Self {
+        Backoff {
+            delay: INITIAL_RECONNECT_BACKOFF,
+        }
+    }
+
+    /// Resets the delay after a successful connection.
+    pub(crate) fn reset(&mut self) {
+        self.delay = INITIAL_RECONNECT_BACKOFF;
+    }
+
+    /// Waits for the next attempt while failing the requests received in the meantime with `error`,
+    /// then doubles the delay.
+    ///
+    /// Returns `false` if the connection handle was dropped and the task should stop.
+    pub(crate) async fn wait(
+        &mut self,
+        requests: &mut mpsc::UnboundedReceiver<QueuedRequest>,
+        error: &ProbeError,
+    ) -> bool {
+        let retry = sleep(self.delay);
+        tokio::pin!(retry);
+        self.delay = (self.delay * 2).min(MAX_RECONNECT_BACKOFF);
+
+        loop {
+            tokio::select! {
+                _ = &mut retry => return true,
+                request = requests.recv() => match request {
+                    Some(request) => {
+                        let _ = request.response.send(Err(error.clone()));
+                    }
+                    None => return false,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::ProbeError,
+        transport::persistent::{PendingRequests, QueuedRequest},
+    };
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn test_pending_requests_expire() {
+        let mut pending = PendingRequests::new(Duration::from_secs(1));
+        let sent_at = Instant::now();
+        let send = |pending: &mut PendingRequests, sent_at| {
+            let (response, receiver) = oneshot::channel();
+            let mut request = QueuedRequest {
+                body: json!({"jsonrpc": "2.0", "method": "eth_chainId", "id": 0}),
+                response,
+            };
+            let (id, _) = pending.prepare(&mut request).unwrap();
+            pending.insert(id, sent_at, request);
+            receiver
+        };
+
+        // Abandoned requests are forgotten before they time out.
+        drop(send(&mut pending, sent_at));
+        let mut receiver = send(&mut pending, sent_at);
+        assert!(!pending.expire(sent_at + Duration::from_millis(500)));
+        assert_eq!(pending.deadline(), Some(sent_at + Duration::from_secs(1)));
+
+        // A request timing out without any traffic since it was sent stalls the connection.
+        assert!(pending.expire(sent_at + Duration::from_secs(1)));
+        assert_eq!(receiver.try_recv().unwrap(), Err(ProbeError::Timeout));
+        assert_eq!(pending.deadline(), None);
+
+        // A request timing out while other responses arrive does not.
+        let _receiver = send(&mut pending, sent_at);
+        pending.received(sent_at + Duration::from_millis(100));
+        assert!(!pending.expire(sent_at + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,170 @@
+// Standard library modules
+use std::time::{Duration, Instant};
+
+// External libraries
+use futures_util::{SinkExt, StreamExt};
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use tokio::{
+    sync::mpsc,
+    time::{sleep_until, timeout},
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        client::IntoClientRequest,
+        handshake::client::Request,
+        http::{header::RETRY_AFTER, HeaderName, HeaderValue, StatusCode},
+        Error as WsError, Message,
+    },
+};
+
+// Internal modules
+use super::{
+    classify_io_error, parse_retry_after,
+    persistent::{Backoff, PendingRequests, PersistentConnection, QueuedRequest},
+};
+use crate::error::ProbeError;
+
+/// Creates a persistent WebSocket connection to the provider at `url`.
+///
+/// Responses are correlated with requests by their `id` and the connection is re-established with an
+/// exponential backoff whenever it is lost or cannot be established within `connect_timeout`. Requests fail
+/// after waiting `response_timeout` for their response, and the connection is re-established if nothing was
+/// received since they were sent. The `headers` are sent with every handshake.
+pub(crate) fn connect(
+    url: &str,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+    headers: HeaderMap,
+) -> PersistentConnection {
+    let url = url.to_string();
+    PersistentConnection::spawn(move |requests| {
+        run(url, connect_timeout, response_timeout, headers, requests)
+    })
+}
+
+/// Keeps a connection to `url` open and forwards requests received on `requests` over it.
+async fn run(
+    url: String,
+    connect_timeout: Duration,
+    response_timeout: Duration,
+    headers: HeaderMap,
+    mut requests: mpsc::UnboundedReceiver<QueuedRequest>,
+) {
+    let mut backoff = Backoff::new();
+
+    loop {
+        // Connect to the provider, rejecting requests while waiting for the next attempt on failure.
+        let request = match handshake_request(&url, &headers) {
+            Ok(request) => request,
+            Err(e) => {
+                if !backoff.wait(&mut requests, &e).await {
+                    return;
+                }
+                continue;
+            }
+        };
+        let connected = timeout(connect_timeout, connect_async(request)).await;
+        let stream = match connected {
+            Ok(Ok((stream, _))) => {
+                backoff.reset();
+                stream
+            }
+            Ok(Err(e)) => {
+                if !backoff.wait(&mut requests, &classify_ws_error(e)).await {
+                    return;
+                }
+                continue;
+            }
+            Err(_) => {
+                if !backoff.wait(&mut requests, &ProbeError::Timeout).await {
+                    return;
+                }
+                continue;
+            }
+        };
+        let (mut sink, mut stream) = stream.split();
+        let mut pending = PendingRequests::new(response_timeout);
+
+        loop {
+            let deadline = pending.deadline();
+            tokio::select! {
+                // Send a request with a unique id over the connection.
+                request = requests.recv() => {
+                    let Some(mut request) = request else {
+                        return;
+                    };
+                    let Some((id, body)) = pending.prepare(&mut request) else {
+                        continue;
+                    };
+
+                    let sent_at = Instant::now();
+                    let sent = sink.send(Message::Text(body)).await;
+                    pending.insert(id, sent_at, request);
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+
+                // Dispatch a response to the request with the same id.
+                message = stream.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        let received_at = Instant::now();
+                        if let Ok(response) = serde_json::from_str::<Value>(&text) {
+                            pending.resolve(response, received_at);
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => pending.received(Instant::now()),
+                },
+
+                // Fail the timed out requests, and reconnect if the provider stopped answering.
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                    if pending.expire(Instant::now()) {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Fail the requests lost with the connection before reconnecting.
+        pending.fail_all(&ProbeError::Connection(
+            "WebSocket connection closed".to_string(),
+        ));
+    }
+}
+
+/// Builds the handshake request to `url` carrying the `headers`.
+fn handshake_request(url: &str, headers: &HeaderMap) -> Result<Request, ProbeError> {
+    let mut request = url.into_client_request().map_err(classify_ws_error)?;
+    for (name, value) in headers {
+        // The headers were validated with another version of the `http` crate, so convert them by bytes.
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_str().as_bytes()),
+            HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            request.headers_mut().append(name, value);
+        }
+    }
+    Ok(request)
+}
+
+/// Classifies an error raised while connecting to a WebSocket provider.
+fn classify_ws_error(error: WsError) -> ProbeError {
+    match error {
+        WsError::Io(e) => classify_io_error(&e),
+        WsError::Tls(e) => ProbeError::Tls(e.to_string()),
+        WsError::Http(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+            ProbeError::RateLimited {
+                retry_after: response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after),
+            }
+        }
+        WsError::Http(response) => ProbeError::HttpStatus(response.status().as_u16()),
+        e => ProbeError::Connection(e.to_string()),
+    }
+}